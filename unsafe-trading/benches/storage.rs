@@ -0,0 +1,68 @@
+//! Compare order storage backends.
+//!
+//! ```sh
+//! cargo bench --bench storage
+//! cargo bench --bench storage --features arena
+//! ```
+
+#![feature(test)]
+
+extern crate test;
+
+use test::Bencher;
+use unsafe_trading::{Amount, LimitPrice, Order, OrderId, OrderKind, OrderSide, TradingEngine};
+
+const ORDERS: u64 = 1_000;
+
+fn resting_orders(trading_engine: &mut TradingEngine) {
+    for id in 1..=ORDERS {
+        let order = Order::new(
+            OrderId::new(id),
+            OrderKind::Limit,
+            OrderSide::Ask,
+            Amount::new(10),
+            LimitPrice::new(10_000 + id % 100),
+        );
+        trading_engine.try_insert(order).unwrap();
+    }
+}
+
+#[bench]
+fn insert_resting_orders(b: &mut Bencher) {
+    b.iter(|| {
+        let mut trading_engine = TradingEngine::default();
+        resting_orders(&mut trading_engine);
+        trading_engine
+    });
+}
+
+#[bench]
+fn sweep_resting_orders(b: &mut Bencher) {
+    b.iter(|| {
+        let mut trading_engine = TradingEngine::default();
+        resting_orders(&mut trading_engine);
+
+        let order = Order::new(
+            OrderId::new(ORDERS + 1),
+            OrderKind::Market,
+            OrderSide::Bid,
+            Amount::new(10 * ORDERS),
+            LimitPrice::new(0),
+        );
+        trading_engine.try_insert(order).unwrap();
+        trading_engine
+    });
+}
+
+#[bench]
+fn cancel_resting_orders(b: &mut Bencher) {
+    b.iter(|| {
+        let mut trading_engine = TradingEngine::default();
+        resting_orders(&mut trading_engine);
+
+        for id in 1..=ORDERS {
+            trading_engine.cancel(&OrderId::new(id)).unwrap();
+        }
+        trading_engine
+    });
+}
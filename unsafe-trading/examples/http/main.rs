@@ -1,6 +1,9 @@
 #![allow(dead_code, unused)]
 
-use unsafe_trading::{Amount, LimitPrice, Order, OrderId, OrderKind, OrderSide, TradingEngine};
+use unsafe_trading::{
+    Amount, ClientOrderId, LimitPrice, Order, OrderId, OrderKind, OrderSide, SystemClock,
+    TradingEngine, TradingEngineError,
+};
 
 use serde::{Deserialize, Serialize};
 
@@ -12,43 +15,64 @@ pub enum OrderRequest {
         side: OrderSide,
         limit_price: u64,
         amount: u64,
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        client_order_id: Option<ClientOrderId>,
+    },
+    Delete {
+        id: u64,
     },
-    Delete(usize),
 }
 
-impl TryInto<Order> for OrderRequest {
-    type Error = ();
-
-    fn try_into(self) -> Result<Order, Self::Error> {
+impl OrderRequest {
+    fn execute(self, trading_engine: &mut TradingEngine) -> Result<OrderId, TradingEngineError> {
         match self {
             OrderRequest::Create {
                 kind,
                 side,
                 limit_price,
                 amount,
-            } => Ok(Order::new(
-                OrderId::new(1),
-                kind,
-                side,
-                Amount::new(amount),
-                LimitPrice::new(limit_price),
-            )),
-            OrderRequest::Delete(_) => Err(()),
+                client_order_id,
+            } => trading_engine.submit(
+                // Engine allocates the actual order id
+                Order::new(
+                    OrderId::new(0),
+                    kind,
+                    side,
+                    Amount::new(amount),
+                    LimitPrice::new(limit_price),
+                ),
+                client_order_id,
+            ),
+            OrderRequest::Delete { id } => trading_engine
+                .cancel(&OrderId::new(id))
+                .map(|_| OrderId::new(id)),
         }
     }
 }
 
 fn main() {
-    let trading_engine = TradingEngine::default();
+    let mut trading_engine = TradingEngine::with_clock(SystemClock);
 
     let order_request = OrderRequest::Create {
         kind: OrderKind::Limit,
         side: OrderSide::Ask,
         limit_price: 10_000,
         amount: 50,
+        client_order_id: Some(ClientOrderId::new("my-first-order")),
     };
     println!("{}", serde_json::to_string_pretty(&order_request).unwrap());
+    let order_id = order_request.execute(&mut trading_engine).unwrap();
 
-    let order: Order = order_request.try_into().unwrap();
+    let order = trading_engine.get(&order_id).unwrap();
     println!("{}", serde_json::to_string_pretty(&order).unwrap());
+
+    let depth = trading_engine.orderbook().depth(10);
+    println!("{}", serde_json::to_string_pretty(&depth).unwrap());
+
+    // Failures carry a reason which can be sent back to the client
+    let order_request = OrderRequest::Delete { id: *order_id + 1 };
+    println!("{}", serde_json::to_string_pretty(&order_request).unwrap());
+    if let Err(error) = order_request.execute(&mut trading_engine) {
+        println!("{}", serde_json::to_string_pretty(&error).unwrap());
+    }
 }
@@ -0,0 +1,125 @@
+//! Time sources for [`TradingEngine`](crate::TradingEngine).
+//!
+//! Engine reads its clock whenever it is handed work, and uses that time to
+//! stamp accepted orders, trades and events and to check order expiry. All
+//! clocks count nanoseconds since Unix epoch.
+
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex, MutexGuard};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+
+pub trait Clock: Debug {
+    /// Current time, in nanoseconds.
+    fn now(&self) -> u128;
+}
+
+/// Wall clock time. It may go backwards when system time is adjusted, which
+/// engine absorbs by never moving its own time backwards.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> u128 {
+        SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map_or(0, |elapsed| elapsed.as_nanos())
+    }
+}
+
+/// Wall clock time read once, then moved forward by a monotonic timer.
+#[derive(Clone, Copy, Debug)]
+pub struct MonotonicClock {
+    origin: Instant,
+    origin_nanos: u128,
+}
+
+impl Default for MonotonicClock {
+    fn default() -> Self {
+        Self {
+            origin: Instant::now(),
+            origin_nanos: SystemClock.now(),
+        }
+    }
+}
+
+impl MonotonicClock {
+    pub fn new() -> Self {
+        Self::default()
+    }
+}
+
+impl Clock for MonotonicClock {
+    fn now(&self) -> u128 {
+        self.origin_nanos + self.origin.elapsed().as_nanos()
+    }
+}
+
+/// Clock which only moves when told to, for deterministic tests and
+/// simulations. Clones share the same time, so a clone kept aside can drive
+/// the one given to engine.
+#[derive(Clone, Debug, Default)]
+pub struct ManualClock {
+    now: Arc<Mutex<u128>>,
+}
+
+impl ManualClock {
+    pub fn new(now: u128) -> Self {
+        Self {
+            now: Arc::new(Mutex::new(now)),
+        }
+    }
+
+    pub fn set(&self, now: u128) {
+        *self.lock() = now;
+    }
+
+    /// Moves time forward by `nanos`, returning the new time.
+    pub fn advance(&self, nanos: u128) -> u128 {
+        let mut now = self.lock();
+        *now += nanos;
+
+        *now
+    }
+
+    fn lock(&self) -> MutexGuard<'_, u128> {
+        // Time is a plain number, so a panic elsewhere cannot leave it torn
+        self.now.lock().unwrap_or_else(|error| error.into_inner())
+    }
+}
+
+impl Clock for ManualClock {
+    fn now(&self) -> u128 {
+        *self.lock()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn manual_clock() {
+        let clock = ManualClock::new(10);
+        let handle = clock.clone();
+
+        assert_eq!(clock.now(), 10);
+        assert_eq!(handle.advance(5), 15);
+        assert_eq!(clock.now(), 15);
+
+        handle.set(100);
+        assert_eq!(clock.now(), 100);
+
+        // Whole range of timestamps is available
+        handle.set(u128::from(u64::MAX));
+        assert_eq!(handle.advance(1), u128::from(u64::MAX) + 1);
+    }
+
+    #[test]
+    fn monotonic_clock() {
+        let clock = MonotonicClock::new();
+        let before = clock.now();
+
+        assert!(before > 0);
+        assert!(clock.now() >= before);
+    }
+}
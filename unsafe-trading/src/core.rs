@@ -1,6 +1,7 @@
+use std::collections::{BTreeMap, BTreeSet, HashMap, VecDeque};
+use std::fmt::Debug;
+use std::ops::{Bound, RangeBounds};
 use std::pin::Pin;
-use std::ptr::NonNull;
-use std::{collections::BTreeMap, fmt::Debug};
 
 use colored::*;
 
@@ -9,26 +10,112 @@ use indexmap::IndexMap;
 #[cfg(feature = "serde")]
 use serde::{Deserialize, Serialize};
 
+use crate::clock::{Clock, ManualClock};
+use crate::error::TradingEngineError;
 use crate::order::{
-    Amount, Exchangeable, LimitPrice, Order, OrderId, OrderKind, OrderSide, OrderStatus, Trade,
+    AccountId, Amount, ClientOrderId, Exchangeable, LimitPrice, Order, OrderId, OrderKind,
+    OrderSide, OrderStatus, PricingPolicy, TimeInForce, Trade, TradeId, TrailingOffset,
 };
+use crate::storage::{OrderHandle, OrderStorage};
 
+/// Matching engine for a single orderbook.
+///
+/// A default engine runs on manual time: its clock stays at zero and only
+/// [`TradingEngine::tick`] moves engine time, so expiry such as
+/// [`TimeInForce::Day`] counts from Unix epoch unless ticked to real time.
+/// Use [`TradingEngine::with_clock`] to follow a [`SystemClock`] or
+/// [`MonotonicClock`] instead.
+///
+/// [`SystemClock`]: crate::SystemClock
+/// [`MonotonicClock`]: crate::MonotonicClock
 #[derive(Debug)]
 #[cfg_attr(feature = "serde", derive(Serialize))]
 pub struct TradingEngine {
     #[cfg_attr(feature = "serde", serde(skip_serializing))]
-    orders: IndexMap<OrderId, Pin<Box<Order>>>,
+    orders: OrderStorage,
     orderbook: Orderbook,
-    events: Vec<TradingEngineResponse>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    stops: StopBook,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    scheduler: Scheduler,
+    last_trade_price: Option<LimitPrice>,
+    pricing_policy: PricingPolicy,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    clock: Box<dyn Clock>,
+    now: u128,
+    events: VecDeque<TradingEngineEvent>,
+    event_limit: Option<usize>,
+    last_sequence: u64,
+    last_trade_id: TradeId,
+    last_order_id: OrderId,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    client_order_ids: HashMap<ClientKey, OrderId>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    client_keys: HashMap<OrderId, ClientKey>,
+    halted: bool,
+}
+
+/// Client order ids are only unique within an account.
+type ClientKey = (Option<AccountId>, ClientOrderId);
+
+/// A [`TradingEngineResponse`] stamped with its position in the engine
+/// event stream.
+///
+/// Sequence numbers start at `1` and grow by one for every event, so a
+/// consumer can detect missed events by looking for holes.
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct TradingEngineEvent {
+    sequence: u64,
+    timestamp: u128,
+    response: TradingEngineResponse,
+}
+
+impl TradingEngineEvent {
+    #[inline]
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Engine time when the event happened, in nanoseconds.
+    #[inline]
+    pub fn timestamp(&self) -> u128 {
+        self.timestamp
+    }
+
+    #[inline]
+    pub fn response(&self) -> &TradingEngineResponse {
+        &self.response
+    }
+
+    #[inline]
+    pub fn into_response(self) -> TradingEngineResponse {
+        self.response
+    }
+}
+
+/// Returned by [`TradingEngine::events_since`] when some of the requested
+/// events are no longer retained by the engine, either because they were
+/// drained or because they were discarded by the event limit.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct EventGap {
+    pub expected: u64,
+    pub first_available: u64,
 }
 
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[allow(clippy::enum_variant_names)]
 pub enum TradingEngineResponse {
     OrderReceived {
         id: OrderId,
     },
     OrderAddedToOrderbook {
         id: OrderId,
+        side: OrderSide,
+        price: LimitPrice,
+        remaining: Amount,
+        created_at: u128,
     },
     OrderPartiallyFilled {
         id: OrderId,
@@ -44,6 +131,60 @@ pub enum TradingEngineResponse {
     OrderRemovedFromOrderbook {
         id: OrderId,
     },
+    OrderCancelled {
+        id: OrderId,
+    },
+    OrderExpired {
+        id: OrderId,
+        remaining: Amount,
+    },
+    OrderScheduled {
+        id: OrderId,
+        activate_at: u128,
+    },
+    OrderRejected {
+        id: OrderId,
+        error: TradingEngineError,
+    },
+    OrderAmended {
+        id: OrderId,
+        limit_price: LimitPrice,
+        previous_remaining: Amount,
+        current_remaining: Amount,
+    },
+    OrdersMassCancelled {
+        count: usize,
+    },
+    LevelUpdated {
+        side: OrderSide,
+        price: LimitPrice,
+        amount: Amount,
+        order_count: usize,
+    },
+    LevelRemoved {
+        side: OrderSide,
+        price: LimitPrice,
+    },
+    OrderAddedToStopBook {
+        id: OrderId,
+    },
+    StopTriggered {
+        id: OrderId,
+        trigger_price: LimitPrice,
+        last_trade_price: LimitPrice,
+    },
+    TrailingStopUpdated {
+        id: OrderId,
+        trigger_price: LimitPrice,
+    },
+    OrderTraded {
+        trade_id: TradeId,
+        maker_id: OrderId,
+        taker_id: OrderId,
+        price: u64,
+        amount: Amount,
+        aggressor_side: OrderSide,
+    },
 }
 
 impl Debug for TradingEngineResponse {
@@ -52,10 +193,10 @@ impl Debug for TradingEngineResponse {
             TradingEngineResponse::OrderReceived { id } => {
                 write!(f, "{} Order {} received", "[BEGIN]".green().bold(), id.0)
             }
-            TradingEngineResponse::OrderAddedToOrderbook { id } => {
-                write!(
+            TradingEngineResponse::OrderAddedToOrderbook { id, .. } => {
+                writeln!(
                     f,
-                    "{}   Order {} added to orderbook\n",
+                    "{}   Order {} added to orderbook",
                     "[END]".cyan().bold(),
                     id.0
                 )
@@ -75,12 +216,99 @@ impl Debug for TradingEngineResponse {
             TradingEngineResponse::OrderRemovedFromOrderbook { id } => {
                 write!(f, "        Order {} removed from orderbook", id.0)
             }
-            TradingEngineResponse::OrderReceivedCompletedBeforeEnterInOrderbook { id } => write!(
+            TradingEngineResponse::OrderReceivedCompletedBeforeEnterInOrderbook { id } => writeln!(
                 f,
-                "{}   Order {} completed before entered in orderbook\n",
+                "{}   Order {} completed before entered in orderbook",
                 "[END]".cyan().bold(),
                 id.0
             ),
+            TradingEngineResponse::OrderCancelled { id } => {
+                writeln!(f, "{}   Order {} cancelled", "[END]".cyan().bold(), id.0)
+            }
+            TradingEngineResponse::OrderExpired { id, remaining } => writeln!(
+                f,
+                "{}   Order {} expired (unfilled: {})",
+                "[END]".cyan().bold(),
+                id.0,
+                remaining.0
+            ),
+            TradingEngineResponse::OrderScheduled { id, activate_at } => writeln!(
+                f,
+                "{}   Order {} scheduled (activation: {})",
+                "[END]".cyan().bold(),
+                id.0,
+                activate_at
+            ),
+            TradingEngineResponse::OrderRejected { id, error } => writeln!(
+                f,
+                "{}   Order {} rejected ({})",
+                "[END]".cyan().bold(),
+                id.0,
+                error
+            ),
+            TradingEngineResponse::OrderAmended {
+                id,
+                limit_price,
+                previous_remaining,
+                current_remaining,
+            } => write!(
+                f,
+                "        Order {} amended (price: {}, current: {}, previous: {})",
+                id.0, limit_price.0, current_remaining.0, previous_remaining.0
+            ),
+            TradingEngineResponse::OrdersMassCancelled { count } => {
+                writeln!(f, "{}   {} orders cancelled", "[END]".cyan().bold(), count)
+            }
+            TradingEngineResponse::LevelUpdated {
+                side,
+                price,
+                amount,
+                order_count,
+            } => write!(
+                f,
+                "        Level {:?} {} updated (amount: {}, orders: {})",
+                side, price.0, amount.0, order_count
+            ),
+            TradingEngineResponse::LevelRemoved { side, price } => {
+                write!(f, "        Level {:?} {} removed", side, price.0)
+            }
+            TradingEngineResponse::OrderAddedToStopBook { id } => {
+                writeln!(
+                    f,
+                    "{}   Order {} added to stop book",
+                    "[END]".cyan().bold(),
+                    id.0
+                )
+            }
+            TradingEngineResponse::StopTriggered {
+                id,
+                trigger_price,
+                last_trade_price,
+            } => write!(
+                f,
+                "{} Order {} triggered (trigger: {}, last trade: {})",
+                "[BEGIN]".green().bold(),
+                id.0,
+                trigger_price.0,
+                last_trade_price.0
+            ),
+            TradingEngineResponse::TrailingStopUpdated { id, trigger_price } => write!(
+                f,
+                "        Order {} trigger price moved to {}",
+                id.0, trigger_price.0
+            ),
+            TradingEngineResponse::OrderTraded {
+                trade_id,
+                maker_id,
+                taker_id,
+                price,
+                amount,
+                aggressor_side,
+            } => write!(
+                f,
+                "        Trade {} executed (maker: {}, taker: {}, amount: {}, price: {}, aggressor: {:?})",
+                trade_id.0, maker_id.0, taker_id.0, amount.0, price, aggressor_side
+            ),
         }
     }
 }
@@ -88,9 +316,22 @@ impl Debug for TradingEngineResponse {
 impl Default for TradingEngine {
     fn default() -> Self {
         Self {
-            orders: IndexMap::with_capacity(1024),
+            orders: OrderStorage::with_capacity(1024),
             orderbook: Orderbook::default(),
-            events: Vec::default(),
+            stops: StopBook::default(),
+            scheduler: Scheduler::default(),
+            last_trade_price: None,
+            pricing_policy: PricingPolicy::default(),
+            clock: Box::new(ManualClock::default()),
+            now: 0,
+            events: VecDeque::default(),
+            event_limit: None,
+            last_sequence: 0,
+            last_trade_id: TradeId(0),
+            last_order_id: OrderId(0),
+            client_order_ids: HashMap::default(),
+            client_keys: HashMap::default(),
+            halted: false,
         }
     }
 }
@@ -102,277 +343,3621 @@ impl TradingEngine {
 
     fn with_capacity(capacity: usize) -> Self {
         Self {
-            orders: IndexMap::with_capacity(capacity),
-            orderbook: Orderbook::default(),
-            events: Vec::default(),
+            orders: OrderStorage::with_capacity(capacity),
+            ..Self::default()
         }
     }
+
+    /// Engine reading time from `clock`. Default engine uses a
+    /// [`ManualClock`] which stays at zero, so only [`TradingEngine::tick`]
+    /// moves its time.
+    pub fn with_clock(clock: impl Clock + 'static) -> Self {
+        let mut trading_engine = Self {
+            clock: Box::new(clock),
+            ..Self::default()
+        };
+        trading_engine.sync_clock();
+
+        trading_engine
+    }
 }
 
 impl TradingEngine {
     fn insert(&mut self, order: Order) {
-        let order_id = order.id;
+        // Keep Order in storage
+        let handle = self.orders.insert(order);
 
-        // Pin Order in heap
-        let mut boxed = Box::pin(order);
+        // Insert Order in orders lists
+        let order = self.orders.resolve(handle);
+        let (side, limit_price) = (order.side, order.limit_price);
+        self.orderbook.insert(handle, order);
 
-        // Get Order address
-        let pin = Pin::as_mut(&mut boxed);
-        let ptr = NonNull::from(pin.get_mut());
+        self.push_level_event(side, limit_price);
+    }
 
-        // Insert Order in index with its pointer
-        self.orders.insert(order_id, boxed);
+    /// Reports the current state of one orderbook level to market data
+    /// consumers.
+    fn push_level_event(&mut self, side: OrderSide, price: LimitPrice) {
+        let response = match self.orderbook.level(side, price) {
+            Some(level) => TradingEngineResponse::LevelUpdated {
+                side,
+                price,
+                amount: level.amount,
+                order_count: level.order_count,
+            },
+            None => TradingEngineResponse::LevelRemoved { side, price },
+        };
 
-        // Insert Order in orders lists
-        self.orderbook.insert(ptr);
+        self.push_event(response);
     }
 
-    pub fn try_insert(&mut self, mut order: Order) -> Result<(), ()> {
-        let order_id = order.id;
+    /// Checks an order on its own, counting orderbook totals as if
+    /// `replaced` was not there.
+    fn validate(&self, order: &Order, replaced: Option<&Order>) -> Result<(), TradingEngineError> {
+        if self.halted {
+            return Err(TradingEngineError::BookHalted);
+        }
 
-        if self.get(&order_id).is_some() {
-            return Err(());
-        }
-
-        self.events
-            .push(TradingEngineResponse::OrderReceived { id: order.id });
-
-        while let Some(mut top_order) = self.pop_from_orderbook(&order) {
-            if let Some(trade) = order.trade(&mut top_order) {
-                let trade_amount = trade.amount;
-                let trade_price = trade.price;
-
-                let (incoming_order_status, top_order_status) = (order.status, top_order.status);
-
-                match (incoming_order_status, top_order_status) {
-                    (OrderStatus::Partial, OrderStatus::Completed) => {
-                        self.events
-                            .push(TradingEngineResponse::OrderPartiallyFilled {
-                                id: order_id,
-                                previous_remaining: order.remaining + trade.amount,
-                                current_remaining: order.remaining,
-                            });
-                        self.events
-                            .push(TradingEngineResponse::OrderCompleted { id: top_order.id });
-                        self.events
-                            .push(TradingEngineResponse::OrderRemovedFromOrderbook {
-                                id: top_order.id,
-                            });
-                        continue;
-                    }
-                    (OrderStatus::Open, OrderStatus::Open)
-                    | (OrderStatus::Open, OrderStatus::Partial)
-                    | (OrderStatus::Partial, OrderStatus::Partial)
-                    | (OrderStatus::Completed, OrderStatus::Partial) => {
-                        self.events
-                            .push(TradingEngineResponse::OrderPartiallyFilled {
-                                id: top_order.id,
-                                previous_remaining: top_order.remaining + trade.amount,
-                                current_remaining: top_order.remaining,
-                            });
-                        self.insert(top_order);
-                        self.events
-                            .push(TradingEngineResponse::OrderCompleted { id: order.id });
-                        break;
-                    }
-                    (OrderStatus::Completed, OrderStatus::Completed) => {
-                        self.events
-                            .push(TradingEngineResponse::OrderCompleted { id: top_order.id });
-                        self.events
-                            .push(TradingEngineResponse::OrderRemovedFromOrderbook {
-                                id: top_order.id,
-                            });
-                        self.events
-                            .push(TradingEngineResponse::OrderCompleted { id: order.id });
-                        break;
-                    }
-                    _ => unreachable!(),
-                }
+        if order.amount.is_zero() || order.remaining.is_zero() {
+            return Err(TradingEngineError::ZeroAmount);
+        }
+
+        if order.remaining > order.amount {
+            return Err(TradingEngineError::RemainingAboveAmount);
+        }
+
+        let length = match replaced {
+            Some(replaced) if replaced.side == order.side && self.orderbook.contains(replaced) => {
+                self.orderbook
+                    .length(order.side)
+                    .saturating_sub(replaced.remaining)
             }
+            _ => self.orderbook.length(order.side),
+        };
+        if length.checked_add(order.remaining).is_none() {
+            return Err(TradingEngineError::AmountOverflow);
         }
 
-        if order.status != OrderStatus::Completed && order.current_kind == OrderKind::Limit {
-            self.events
-                .push(TradingEngineResponse::OrderAddedToOrderbook { id: order.id });
-            self.insert(order);
-        } else {
-            self.events.push(
-                TradingEngineResponse::OrderReceivedCompletedBeforeEnterInOrderbook {
-                    id: order.id,
-                },
-            );
+        let has_trigger_price = order.trigger_price.is_some_and(|price| price.0 != 0);
+
+        match order.current_kind {
+            OrderKind::Limit | OrderKind::StopLimit if order.limit_price.0 == 0 => {
+                return Err(TradingEngineError::InvalidPrice)
+            }
+            OrderKind::Stop | OrderKind::StopLimit if !has_trigger_price => {
+                return Err(TradingEngineError::InvalidPrice)
+            }
+            OrderKind::Trailing
+                if order
+                    .trailing_offset
+                    .is_none_or(|trailing_offset| trailing_offset.is_zero()) =>
+            {
+                return Err(TradingEngineError::InvalidPrice)
+            }
+            OrderKind::Limit
+            | OrderKind::Market
+            | OrderKind::Stop
+            | OrderKind::StopLimit
+            | OrderKind::Trailing => (),
+        }
+
+        if let TimeInForce::Gtd(expires_at) = order.time_in_force {
+            if expires_at <= self.now {
+                return Err(TradingEngineError::InvalidExpiry);
+            }
         }
 
         Ok(())
     }
 
-    pub fn remove(&mut self, order_id: &OrderId) -> Option<Order> {
-        let pin = self.orders.remove(order_id)?;
-        Some(*Pin::into_inner(pin))
-    }
+    fn validate_id(&self, order_id: &OrderId) -> Result<(), TradingEngineError> {
+        if self.get(order_id).is_some() || self.scheduler.contains(order_id) {
+            return Err(TradingEngineError::DuplicateOrderId { id: *order_id });
+        }
 
-    #[must_use]
-    pub fn get(&self, order_id: &OrderId) -> Option<&Order> {
-        let order = self.orders.get(order_id)?;
+        Ok(())
+    }
 
-        // SAFETY: if Order is in indexes, it should be a valid pointer.
-        unsafe { Some(Pin::into_inner(order.as_ref())) }
+    /// Keeps a stop order aside, out of orderbook, until it is triggered.
+    fn park(&mut self, order: Order) {
+        self.stops.insert(&order);
+        self.orders.insert(order);
     }
 
-    #[must_use]
-    pub fn get_mut(&mut self, order_id: &OrderId) -> Option<&mut Order> {
-        let order = self.orders.get_mut(order_id)?;
+    pub fn try_insert(&mut self, mut order: Order) -> Result<(), TradingEngineError> {
+        self.sync_clock();
+        self.validate(&order, None)?;
+        self.validate_id(&order.id)?;
+        self.prepare(&mut order, None)?;
+        self.accept(order);
 
-        // SAFETY: if Order is in indexes, it should be a valid pointer.
-        unsafe { Some(Pin::into_inner(order.as_mut())) }
+        Ok(())
     }
 
-    #[must_use]
-    pub fn pop_from_orderbook(&mut self, opposite_order: &Order) -> Option<Order> {
-        let order = self.orderbook.pop(opposite_order)?;
-        let order_id = unsafe { order.as_ref() }.id;
+    /// Accepts `order` under an order id allocated by engine, which is
+    /// returned. Whatever id `order` carries is replaced.
+    ///
+    /// `client_order_id`, if any, lets the order be found by
+    /// [`TradingEngine::get_by_client_order_id`] while it is live, and must
+    /// not be in use by another live order of the same account.
+    pub fn submit(
+        &mut self,
+        mut order: Order,
+        client_order_id: Option<ClientOrderId>,
+    ) -> Result<OrderId, TradingEngineError> {
+        self.sync_clock();
+        self.validate(&order, None)?;
 
-        self.remove(&order_id)
-    }
-}
+        let client_key = client_order_id.map(|client_order_id| (order.account, client_order_id));
+        if client_key
+            .as_ref()
+            .is_some_and(|client_key| self.client_order_ids.contains_key(client_key))
+        {
+            return Err(TradingEngineError::DuplicateClientOrderId);
+        }
 
-type Orders = BTreeMap<OrderId, NonNull<Order>>;
-type Levels = BTreeMap<LimitPrice, Orders>;
-type Sides = IndexMap<OrderSide, Levels>;
+        self.prepare(&mut order, None)?;
 
-#[derive(Debug)]
-#[cfg_attr(feature = "serde", derive(Serialize))]
-pub struct Orderbook {
-    #[cfg_attr(feature = "serde", serde(skip_serializing))]
-    sides: Sides,
-    ask_length: Amount,
-    bid_length: Amount,
-}
+        order.id = self.next_order_id();
+        if let Some(client_key) = client_key {
+            self.client_order_ids.insert(client_key.clone(), order.id);
+            self.client_keys.insert(order.id, client_key);
+        }
 
-impl Default for Orderbook {
-    fn default() -> Self {
-        let mut sides = IndexMap::new();
-        sides.insert(OrderSide::Ask, BTreeMap::default());
-        sides.insert(OrderSide::Bid, BTreeMap::default());
+        let order_id = order.id;
+        self.accept(order);
 
-        Self {
-            sides,
-            ask_length: Amount(0),
-            bid_length: Amount(0),
+        Ok(order_id)
+    }
+
+    /// Cancels a live order and submits `order` in its place as a single
+    /// operation, returning the final state of the cancelled order.
+    ///
+    /// Nothing changes if either side of it fails: the old order must still
+    /// be live and the new one must be accepted. The new order may reuse the
+    /// old order id.
+    pub fn cancel_replace(
+        &mut self,
+        order_id: &OrderId,
+        mut order: Order,
+    ) -> Result<Order, TradingEngineError> {
+        self.sync_clock();
+        if self.get(order_id).is_none() && !self.scheduler.contains(order_id) {
+            return Err(TradingEngineError::UnknownOrder { id: *order_id });
+        }
+
+        // Old order is still resting, so keep it out of the checks
+        self.validate(&order, self.get(order_id))?;
+        if order.id != *order_id {
+            self.validate_id(&order.id)?;
         }
+        self.prepare(&mut order, self.get(order_id))?;
+
+        let cancelled = self.cancel(order_id)?;
+        self.accept(order);
+
+        Ok(cancelled)
     }
-}
 
-impl Orderbook {
-    fn pop(&mut self, incoming_order: &Order) -> Option<NonNull<Order>> {
-        let opposite_side = incoming_order.side.opposite();
+    /// Last checks that depend on orderbook state, made before an order is
+    /// accepted. Orderbook is looked at as if `replaced` was not there.
+    fn prepare(
+        &self,
+        order: &mut Order,
+        replaced: Option<&Order>,
+    ) -> Result<(), TradingEngineError> {
+        // Fill or kill orders are rejected as a whole before anything trades
+        if order.time_in_force == TimeInForce::Fok
+            && !order.is_pending()
+            && !self.orderbook.can_fill(order, replaced)
+        {
+            return Err(TradingEngineError::InsufficientLiquidity);
+        }
 
-        let (_level_limit_price, orders) = match incoming_order.side {
-            OrderSide::Ask => self.sides.get(&opposite_side)?.iter().rev().next()?,
-            OrderSide::Bid => self.sides.get(&opposite_side)?.iter().next()?,
-        };
+        if order.current_kind == OrderKind::Trailing && order.trigger_price.is_none() {
+            // Trailing stop orders start following last trade price or, if
+            // nothing has been traded yet, best price they would trade with
+            let reference = self
+                .last_trade_price
+                .or_else(|| self.orderbook.best_price(order.side.opposite(), replaced))
+                .ok_or(TradingEngineError::InvalidPrice)?;
 
-        let (_order_id, order) = orders.iter().next()?;
-        let order = unsafe { order.as_ref() };
+            order.trail(reference);
+        }
 
-        self.remove(order)
+        Ok(())
     }
 
-    fn insert(&mut self, order: NonNull<Order>) {
-        // Matching algorithm
-        let (id, side, limit_price, remaining) = {
-            let order = unsafe { order.as_ref() };
+    fn accept(&mut self, mut order: Order) {
+        order.created_at = self.now;
+        order.expires_at = order.time_in_force.expires_at(self.now);
 
-            (order.id, order.side, order.limit_price, order.remaining)
-        };
+        let (order_id, expires_at) = (order.id, order.expires_at);
+        self.push_event(TradingEngineResponse::OrderReceived { id: order_id });
 
-        match side {
-            OrderSide::Ask => self.ask_length += remaining,
-            OrderSide::Bid => self.bid_length += remaining,
+        if order.is_pending() {
+            self.push_event(TradingEngineResponse::OrderAddedToStopBook { id: order_id });
+            self.park(order);
+        } else {
+            self.execute(order);
         }
 
-        self.sides
-            .entry(side)
-            .or_insert_with(Default::default)
-            .entry(limit_price)
-            .or_insert_with(Default::default)
-            .insert(id, order);
+        // Only orders left resting or parked have anything to expire
+        if let Some(expires_at) = expires_at.filter(|_| self.get(&order_id).is_some()) {
+            self.scheduler.schedule_expiry(order_id, expires_at);
+        }
+
+        self.trigger_stops();
     }
 
-    fn remove(&mut self, order: &Order) -> Option<NonNull<Order>> {
-        // Remove remaing orders from total count
-        match order.side {
-            OrderSide::Ask => self.ask_length -= order.remaining,
-            OrderSide::Bid => self.bid_length -= order.remaining,
+    /// Holds `order` back until engine time reaches `activate_at`, then
+    /// submits it as [`TradingEngine::try_insert`] would. Orders due already
+    /// are submitted right away.
+    pub fn schedule(
+        &mut self,
+        mut order: Order,
+        activate_at: u128,
+    ) -> Result<(), TradingEngineError> {
+        self.sync_clock();
+        if activate_at <= self.now {
+            return self.try_insert(order);
         }
 
-        let side = order.side;
-        let limit_price = order.limit_price;
+        self.validate(&order, None)?;
+        self.validate_id(&order.id)?;
 
-        // Remove order from tree
-        let level = self.sides.get_mut(&side)?.get_mut(&limit_price)?;
-        let ptr = level.remove(&order.id);
+        order.created_at = activate_at;
 
-        // If level is empty, remove it
-        if level.is_empty() {
-            self.sides.get_mut(&side)?.remove(&limit_price)?;
+        self.push_event(TradingEngineResponse::OrderScheduled {
+            id: order.id,
+            activate_at,
+        });
+        self.scheduler.insert(Box::pin(order));
+
+        Ok(())
+    }
+
+    /// Cancels an order once engine time reaches `at`, or right away if that
+    /// time has come already.
+    pub fn schedule_cancel(
+        &mut self,
+        order_id: OrderId,
+        at: u128,
+    ) -> Result<(), TradingEngineError> {
+        self.sync_clock();
+        if self.get(&order_id).is_none() && !self.scheduler.contains(&order_id) {
+            return Err(TradingEngineError::UnknownOrder { id: order_id });
+        }
+
+        if at <= self.now {
+            return self.cancel(&order_id).map(|_| ());
         }
 
-        ptr
+        self.scheduler.schedule_cancel(order_id, at);
+
+        Ok(())
     }
-}
 
-#[derive(Debug)]
-#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
-pub struct Scheduler;
+    /// Activates every stop order crossed by last trade price. Trades made by
+    /// triggered orders move the price as well, so this goes on until no stop
+    /// order is left to trigger.
+    fn trigger_stops(&mut self) {
+        while let Some(last_trade_price) = self.last_trade_price {
+            let Some(order_id) = self.stops.pop_triggered(last_trade_price) else {
+                break;
+            };
+            let Some(mut order) = self.take(&order_id) else {
+                continue;
+            };
 
-impl Scheduler {
-    pub fn insert(&mut self, order: Pin<Box<Order>>) {
-        todo!()
+            self.push_event(TradingEngineResponse::StopTriggered {
+                id: order_id,
+                trigger_price: order.trigger_price.unwrap_or(last_trade_price),
+                last_trade_price,
+            });
+
+            // Triggered order queues from now, like any order arriving now
+            order.activate();
+            order.created_at = self.now;
+            self.execute(order);
+        }
     }
-    pub fn remove(&mut self, order: &Order) -> Option<Order> {
-        todo!()
+
+    /// Moves trailing stop orders along with `reference` price.
+    fn trail_stops(&mut self, reference: LimitPrice) {
+        let trailing = self.stops.trailing.iter().copied().collect::<Vec<_>>();
+
+        for order_id in trailing {
+            let Some(order) = self.orders.get_mut(&order_id) else {
+                continue;
+            };
+
+            self.stops.remove(order);
+            let moved = order.trail(reference);
+            self.stops.insert(order);
+
+            if let (true, Some(trigger_price)) = (moved, order.trigger_price) {
+                self.push_event(TradingEngineResponse::TrailingStopUpdated {
+                    id: order_id,
+                    trigger_price,
+                });
+            }
+        }
     }
-}
 
-#[cfg(test)]
-mod tests {
-    use std::time;
+    /// Matches an accepted order against orderbook, resting whatever is left
+    /// of it if that is allowed.
+    fn execute(&mut self, mut order: Order) {
+        let order_id = order.id;
 
-    use super::*;
+        // Triggered stop orders cannot be rejected anymore, so an unfillable
+        // fill or kill order just expires
+        if order.time_in_force == TimeInForce::Fok && !self.orderbook.can_fill(&order, None) {
+            order.cancel();
+            self.release(&order_id);
+            self.push_event(TradingEngineResponse::OrderExpired {
+                id: order_id,
+                remaining: order.remaining,
+            });
+            return;
+        }
 
-    const EXAMPLE_ORDER: Order = Order {
-        id: OrderId(1),
-        side: OrderSide::Ask,
-        amount: Amount(100),
-        remaining: Amount(100),
-        limit_price: LimitPrice(500),
-        initial_kind: OrderKind::Limit,
-        current_kind: OrderKind::Limit,
-        status: OrderStatus::Open,
-        created_at: 0,
-    };
+        while let Some(handle) = self.orderbook.peek(&order) {
+            let top_order = self.orders.resolve_mut(handle);
 
-    #[test]
-    fn it_works() {
-        let mut trading_engine = TradingEngine::with_capacity(1024);
-        for i in 1..=11 {
-            let mut order = EXAMPLE_ORDER;
-            order.id = OrderId(i);
-            assert!(trading_engine.try_insert(order).is_ok());
+            // Best opposite level does not cross, so nothing else will
+            let Some(mut trade) = order.trade_with(top_order, self.pricing_policy) else {
+                break;
+            };
+            trade.created_at = self.now;
+
+            // Top order stays in place, only its remaining changes
+            self.orderbook.reduce(top_order, trade.amount);
+
+            let (top_order_id, top_order_status, top_order_remaining, top_order_limit_price) = (
+                top_order.id,
+                top_order.status,
+                top_order.remaining,
+                top_order.limit_price,
+            );
+            let incoming_order_status = order.status;
+
+            let trade_amount = trade.amount;
+            let trade_price = trade.price;
+
+            let trade_id = self.next_trade_id();
+            self.push_event(TradingEngineResponse::OrderTraded {
+                trade_id,
+                maker_id: trade.maker_id(),
+                taker_id: trade.taker_id(),
+                price: trade_price,
+                amount: trade_amount,
+                aggressor_side: trade.aggressor_side(),
+            });
+
+            // Trailing stops follow the trade, so they move after it is told
+            self.last_trade_price = Some(LimitPrice(trade_price));
+            self.trail_stops(LimitPrice(trade_price));
+
+            match (incoming_order_status, top_order_status) {
+                (OrderStatus::Partial, OrderStatus::Completed) => {
+                    self.push_event(TradingEngineResponse::OrderPartiallyFilled {
+                        id: order_id,
+                        previous_remaining: order.remaining + trade_amount,
+                        current_remaining: order.remaining,
+                    });
+                    self.push_event(TradingEngineResponse::OrderCompleted { id: top_order_id });
+                    self.take(&top_order_id);
+                    self.release(&top_order_id);
+                    self.push_event(TradingEngineResponse::OrderRemovedFromOrderbook {
+                        id: top_order_id,
+                    });
+                    continue;
+                }
+                (OrderStatus::Completed, OrderStatus::Partial) => {
+                    self.push_event(TradingEngineResponse::OrderPartiallyFilled {
+                        id: top_order_id,
+                        previous_remaining: top_order_remaining + trade_amount,
+                        current_remaining: top_order_remaining,
+                    });
+                    self.push_level_event(order.side.opposite(), top_order_limit_price);
+                    self.push_event(TradingEngineResponse::OrderCompleted { id: order_id });
+                    break;
+                }
+                (OrderStatus::Completed, OrderStatus::Completed) => {
+                    self.push_event(TradingEngineResponse::OrderCompleted { id: top_order_id });
+                    self.take(&top_order_id);
+                    self.release(&top_order_id);
+                    self.push_event(TradingEngineResponse::OrderRemovedFromOrderbook {
+                        id: top_order_id,
+                    });
+                    self.push_event(TradingEngineResponse::OrderCompleted { id: order_id });
+                    break;
+                }
+                _ => unreachable!(),
+            }
         }
 
-        for i in 12..=17 {
-            let mut order = EXAMPLE_ORDER;
-            order.id = OrderId(i);
-            order.side = OrderSide::Bid;
-            order.amount = Amount(200);
-            order.remaining = order.amount;
-            assert!(trading_engine.try_insert(order).is_ok());
+        match order.status {
+            OrderStatus::Completed => {
+                self.release(&order_id);
+                self.push_event(
+                    TradingEngineResponse::OrderReceivedCompletedBeforeEnterInOrderbook {
+                        id: order.id,
+                    },
+                );
+            }
+            _ if order.is_market() || !order.time_in_force.can_rest() => {
+                // Market, immediate or cancel and fill or kill orders never
+                // rest, so whatever liquidity could not fill is dropped
+                order.cancel();
+                self.release(&order_id);
+                self.push_event(TradingEngineResponse::OrderExpired {
+                    id: order.id,
+                    remaining: order.remaining,
+                });
+            }
+            _ if self
+                .orderbook
+                .length(order.side)
+                .checked_add(order.remaining)
+                .is_none() =>
+            {
+                // Triggered stop orders are checked only when received, so
+                // orderbook may have grown too large to take them since
+                order.cancel();
+                self.release(&order_id);
+                self.push_event(TradingEngineResponse::OrderRejected {
+                    id: order.id,
+                    error: TradingEngineError::AmountOverflow,
+                });
+            }
+            _ => {
+                self.push_event(TradingEngineResponse::OrderAddedToOrderbook {
+                    id: order.id,
+                    side: order.side,
+                    price: order.limit_price,
+                    remaining: order.remaining,
+                    created_at: order.created_at,
+                });
+                self.insert(order);
+            }
         }
+    }
 
-        for event in &trading_engine.events {
-            eprintln!("{:?}", event);
+    fn push_event(&mut self, response: TradingEngineResponse) {
+        self.last_sequence += 1;
+        self.events.push_back(TradingEngineEvent {
+            sequence: self.last_sequence,
+            timestamp: self.now,
+            response,
+        });
+
+        if let Some(limit) = self.event_limit {
+            while self.events.len() > limit {
+                self.events.pop_front();
+            }
         }
     }
+
+    /// Next order id not taken by a live order. Ids given by callers of
+    /// [`TradingEngine::try_insert`] are skipped.
+    fn next_order_id(&mut self) -> OrderId {
+        loop {
+            self.last_order_id.0 += 1;
+
+            if self.get(&self.last_order_id).is_none()
+                && !self.scheduler.contains(&self.last_order_id)
+            {
+                return self.last_order_id;
+            }
+        }
+    }
+
+    /// Forgets client order id and scheduled cancels of an order which left
+    /// engine for good, so none of them reach a later order reusing its id.
+    fn release(&mut self, order_id: &OrderId) {
+        if let Some(client_key) = self.client_keys.remove(order_id) {
+            self.client_order_ids.remove(&client_key);
+        }
+
+        self.scheduler.remove_cancels(order_id);
+    }
+
+    fn next_trade_id(&mut self) -> TradeId {
+        self.last_trade_id.0 += 1;
+        self.last_trade_id
+    }
+
+    fn take(&mut self, order_id: &OrderId) -> Option<Order> {
+        let Some(order) = self.orders.get(order_id) else {
+            return self.scheduler.remove(order_id);
+        };
+
+        // Unlink Order from orderbook before it leaves storage, so no stale
+        // handle is left behind
+        let (side, limit_price) = (order.side, order.limit_price);
+        let unlinked = self.orderbook.remove(order).is_some();
+        self.stops.remove(order);
+
+        if unlinked {
+            self.push_level_event(side, limit_price);
+        }
+
+        self.orders.remove(order_id)
+    }
+
+    pub fn remove(&mut self, order_id: &OrderId) -> Result<Order, TradingEngineError> {
+        let order = self.withdraw(order_id)?;
+
+        self.push_event(TradingEngineResponse::OrderRemovedFromOrderbook { id: order.id });
+
+        Ok(order)
+    }
+
+    /// Cancels an order, unlinking it from the orderbook, and returns its
+    /// final state.
+    pub fn cancel(&mut self, order_id: &OrderId) -> Result<Order, TradingEngineError> {
+        let mut order = self.withdraw(order_id)?;
+        order.cancel();
+
+        self.push_event(TradingEngineResponse::OrderCancelled { id: order.id });
+
+        Ok(order)
+    }
+
+    /// Takes an order out of engine for good, leaving it to the caller to
+    /// tell why.
+    fn withdraw(&mut self, order_id: &OrderId) -> Result<Order, TradingEngineError> {
+        self.sync_clock();
+        let order = self
+            .take(order_id)
+            .ok_or(TradingEngineError::UnknownOrder { id: *order_id })?;
+        self.release(order_id);
+
+        Ok(order)
+    }
+
+    /// Cancels every order resting on `side` of orderbook, along with stop
+    /// orders of that side which have not been triggered.
+    pub fn cancel_side(&mut self, side: OrderSide) -> Vec<Order> {
+        let order_ids = self
+            .orderbook
+            .handles(side, ..)
+            .map(|handle| self.orders.resolve(handle).id)
+            .chain(
+                self.orders
+                    .values()
+                    .filter(|order| order.side == side && order.is_pending())
+                    .map(|order| order.id),
+            )
+            .collect();
+
+        self.cancel_many(order_ids)
+    }
+
+    /// Cancels every order resting on `side` of orderbook whose limit price
+    /// falls within `range`. Stop orders are left alone, as they do not rest
+    /// anywhere yet, and so is everything when `range` is inverted.
+    pub fn cancel_price_range<R>(&mut self, side: OrderSide, range: R) -> Vec<Order>
+    where
+        R: RangeBounds<LimitPrice>,
+    {
+        let order_ids = self
+            .orderbook
+            .handles(side, range)
+            .map(|handle| self.orders.resolve(handle).id)
+            .collect();
+
+        self.cancel_many(order_ids)
+    }
+
+    /// Cancels every live order of `account`, including stop orders which
+    /// have not been triggered and orders waiting for their activation.
+    pub fn cancel_account(&mut self, account: AccountId) -> Vec<Order> {
+        let order_ids = self
+            .orders
+            .values()
+            .chain(self.scheduler.orders())
+            .filter(|order| order.account == Some(account))
+            .map(|order| order.id)
+            .collect();
+
+        self.cancel_many(order_ids)
+    }
+
+    /// Cancels every live order, including stop orders which have not been
+    /// triggered and orders waiting for their activation.
+    pub fn cancel_all(&mut self) -> Vec<Order> {
+        let order_ids = self
+            .orders
+            .values()
+            .chain(self.scheduler.orders())
+            .map(|order| order.id)
+            .collect();
+
+        self.cancel_many(order_ids)
+    }
+
+    /// Cancels each order one by one, then reports how many were cancelled.
+    fn cancel_many(&mut self, order_ids: Vec<OrderId>) -> Vec<Order> {
+        let orders: Vec<Order> = order_ids
+            .iter()
+            .filter_map(|order_id| self.cancel(order_id).ok())
+            .collect();
+
+        self.push_event(TradingEngineResponse::OrdersMassCancelled {
+            count: orders.len(),
+        });
+
+        orders
+    }
+
+    /// Changes limit price and total amount of a resting order.
+    ///
+    /// Order keeps its place in queue when only its amount decreases. A new
+    /// price or a larger amount sends it to the back of its level, matching
+    /// it again first in case the new price crosses.
+    pub fn amend(
+        &mut self,
+        order_id: &OrderId,
+        limit_price: LimitPrice,
+        amount: Amount,
+    ) -> Result<(), TradingEngineError> {
+        self.sync_clock();
+        if self.halted {
+            return Err(TradingEngineError::BookHalted);
+        }
+
+        let order = self
+            .orders
+            .get(order_id)
+            .ok_or(TradingEngineError::UnknownOrder { id: *order_id })?;
+
+        // Stop orders wait out of orderbook, where there is no queue to keep
+        if !self.orderbook.contains(order) {
+            return Err(TradingEngineError::UnsupportedOrderKind {
+                kind: order.current_kind,
+            });
+        }
+
+        if amount.is_zero() {
+            return Err(TradingEngineError::ZeroAmount);
+        }
+
+        if limit_price.0 == 0 {
+            return Err(TradingEngineError::InvalidPrice);
+        }
+
+        let filled = order.amount - order.remaining;
+        if amount <= filled {
+            return Err(TradingEngineError::InvalidAmount);
+        }
+
+        let remaining = amount - filled;
+        if remaining > order.remaining
+            && self
+                .orderbook
+                .length(order.side)
+                .checked_add(remaining - order.remaining)
+                .is_none()
+        {
+            return Err(TradingEngineError::AmountOverflow);
+        }
+
+        let keeps_priority = limit_price == order.limit_price && remaining <= order.remaining;
+        self.push_event(TradingEngineResponse::OrderAmended {
+            id: *order_id,
+            limit_price,
+            previous_remaining: order.remaining,
+            current_remaining: remaining,
+        });
+
+        if keeps_priority {
+            if let Some(order) = self.orders.get_mut(order_id) {
+                self.orderbook.reduce(order, order.remaining - remaining);
+                order.update(|order| {
+                    order.amount = amount;
+                    order.remaining = remaining;
+                });
+
+                let (side, limit_price) = (order.side, order.limit_price);
+                self.push_level_event(side, limit_price);
+            }
+        } else if let Some(mut order) = self.take(order_id) {
+            order.update(|order| {
+                order.limit_price = limit_price;
+                order.amount = amount;
+                order.remaining = remaining;
+            });
+
+            // Order queues again from now, so its timestamp tells as much
+            order.created_at = self.now;
+
+            self.execute(order);
+            self.trigger_stops();
+        }
+
+        Ok(())
+    }
+
+    /// Stops accepting new orders. Resting orders can still be cancelled.
+    pub fn halt(&mut self) {
+        self.halted = true;
+    }
+
+    pub fn resume(&mut self) {
+        self.halted = false;
+    }
+
+    #[inline]
+    pub fn is_halted(&self) -> bool {
+        self.halted
+    }
+
+    /// Catches engine time up with its clock. Engine time never goes
+    /// backwards, even if its clock does.
+    fn sync_clock(&mut self) {
+        self.now = self.now.max(self.clock.now());
+    }
+
+    /// Runs every scheduled task due by current clock time, as
+    /// [`TradingEngine::tick`] does.
+    pub fn poll(&mut self) {
+        self.tick(self.clock.now());
+    }
+
+    /// Current engine time, in nanoseconds.
+    #[inline]
+    pub fn now(&self) -> u128 {
+        self.now
+    }
+
+    /// Advances engine time to `now`, running every scheduled task due by
+    /// then in time order. Each task runs with engine time set to the time it
+    /// was scheduled to. Engine time never goes backwards.
+    pub fn tick(&mut self, now: u128) {
+        let now = self.now.max(now);
+
+        while let Some((at, task)) = self.scheduler.pop_due(now) {
+            self.now = self.now.max(at);
+
+            match task {
+                ScheduledTask::Activate(order) => {
+                    let order = *Pin::into_inner(order);
+                    let order_id = order.id;
+
+                    if let Err(error) = self.try_insert(order) {
+                        // Order never made it, but another one may still be
+                        // alive under its id
+                        if self.get(&order_id).is_none() {
+                            self.scheduler.remove_cancels(&order_id);
+                        }
+
+                        self.push_event(TradingEngineResponse::OrderRejected {
+                            id: order_id,
+                            error,
+                        });
+                    }
+                }
+                ScheduledTask::Expire(order_id) => {
+                    // Expiry is not unscheduled when an order leaves the
+                    // engine, so make sure it is still the same order
+                    let expired = self
+                        .get(&order_id)
+                        .is_some_and(|order| order.expires_at == Some(at));
+
+                    if expired {
+                        self.expire(&order_id);
+                    }
+                }
+                ScheduledTask::Cancel(order_id) => {
+                    let _ = self.cancel(&order_id);
+                }
+            }
+        }
+
+        // Activated orders may have read a clock ahead of `now` already
+        self.now = self.now.max(now);
+    }
+
+    fn expire(&mut self, order_id: &OrderId) {
+        let Some(mut order) = self.take(order_id) else {
+            return;
+        };
+        self.release(order_id);
+
+        order.cancel();
+        self.push_event(TradingEngineResponse::OrderExpired {
+            id: order.id,
+            remaining: order.remaining,
+        });
+    }
+
+    /// Price of the last trade executed, if any.
+    #[inline]
+    pub fn last_trade_price(&self) -> Option<LimitPrice> {
+        self.last_trade_price
+    }
+
+    /// How crossing limit orders are priced.
+    #[inline]
+    pub fn pricing_policy(&self) -> PricingPolicy {
+        self.pricing_policy
+    }
+
+    /// Changes how crossing limit orders are priced from now on. Trades
+    /// already executed keep their price.
+    pub fn set_pricing_policy(&mut self, policy: PricingPolicy) {
+        self.pricing_policy = policy;
+    }
+
+    /// Sequence number of the last event emitted, or `0` if there is none.
+    #[inline]
+    pub fn last_sequence(&self) -> u64 {
+        self.last_sequence
+    }
+
+    /// Bounds how many events are retained, discarding the oldest ones first.
+    /// `None` retains every event until it is drained.
+    pub fn set_event_limit(&mut self, limit: Option<usize>) {
+        self.event_limit = limit;
+
+        if let Some(limit) = limit {
+            let excess = self.events.len().saturating_sub(limit);
+            self.events.drain(..excess);
+        }
+    }
+
+    /// Removes every retained event, in sequence order.
+    pub fn drain_events(&mut self) -> impl Iterator<Item = TradingEngineEvent> + '_ {
+        self.events.drain(..)
+    }
+
+    /// Retained events with a sequence number greater than `sequence`.
+    ///
+    /// Fails if any of those events are no longer retained, so consumers
+    /// never silently skip part of the stream.
+    pub fn events_since(
+        &self,
+        sequence: u64,
+    ) -> Result<impl Iterator<Item = &TradingEngineEvent>, EventGap> {
+        let first_available = self.last_sequence + 1 - self.events.len() as u64;
+        // Sequence comes from consumers, so it may be anything
+        let expected = sequence.saturating_add(1);
+
+        if expected < first_available {
+            return Err(EventGap {
+                expected,
+                first_available,
+            });
+        }
+
+        let skip = (expected - first_available) as usize;
+
+        Ok(self.events.iter().skip(skip))
+    }
+
+    /// Orders resting on `side` of orderbook, best price first and in time
+    /// priority within a level.
+    pub fn resting_orders(&self, side: OrderSide) -> impl Iterator<Item = &Order> + '_ {
+        self.orderbook
+            .levels(side)
+            .flat_map(|(_limit_price, level)| level.orders.values())
+            .map(|handle| self.orders.resolve(*handle))
+    }
+
+    /// Full order by order view of orderbook.
+    pub fn snapshot(&self) -> BookSnapshot {
+        let side = |side| {
+            self.orderbook
+                .levels(side)
+                .map(|(price, level)| LevelSnapshot {
+                    price: *price,
+                    orders: level
+                        .orders
+                        .values()
+                        .map(|handle| {
+                            let order = self.orders.resolve(*handle);
+
+                            OrderSnapshot {
+                                id: order.id,
+                                remaining: order.remaining,
+                                created_at: order.created_at,
+                            }
+                        })
+                        .collect(),
+                })
+                .collect()
+        };
+
+        BookSnapshot {
+            sequence: self.last_sequence,
+            asks: side(OrderSide::Ask),
+            bids: side(OrderSide::Bid),
+        }
+    }
+
+    /// Read only view of resting orders.
+    #[inline]
+    pub fn orderbook(&self) -> &Orderbook {
+        &self.orderbook
+    }
+
+    #[must_use]
+    pub fn get(&self, order_id: &OrderId) -> Option<&Order> {
+        self.orders.get(order_id)
+    }
+
+    /// Live order of `account` submitted under `client_order_id`.
+    #[must_use]
+    pub fn get_by_client_order_id(
+        &self,
+        account: Option<AccountId>,
+        client_order_id: &ClientOrderId,
+    ) -> Option<&Order> {
+        let order_id = self
+            .client_order_ids
+            .get(&(account, client_order_id.clone()))?;
+
+        self.get(order_id)
+    }
+
+    /// Client order id a live order was submitted under, if any.
+    #[must_use]
+    pub fn client_order_id(&self, order_id: &OrderId) -> Option<&ClientOrderId> {
+        self.client_keys
+            .get(order_id)
+            .map(|(_account, client_order_id)| client_order_id)
+    }
+
+    #[must_use]
+    pub fn get_mut(&mut self, order_id: &OrderId) -> Option<&mut Order> {
+        self.orders.get_mut(order_id)
+    }
+
+    #[must_use]
+    pub fn pop_from_orderbook(&mut self, opposite_order: &Order) -> Option<Order> {
+        let handle = self.orderbook.peek(opposite_order)?;
+        let order_id = self.orders.resolve(handle).id;
+        let order = self.take(&order_id)?;
+        self.release(&order_id);
+
+        self.push_event(TradingEngineResponse::OrderRemovedFromOrderbook { id: order_id });
+
+        Some(order)
+    }
+}
+
+/// Orders in a level, keyed by a priority which orderbook hands out on every
+/// insertion. Queue position follows arrival order, whatever the order id.
+type Orders = BTreeMap<u64, OrderHandle>;
+type Levels = BTreeMap<LimitPrice, Level>;
+type Sides = IndexMap<OrderSide, Levels>;
+
+/// Orders resting at one limit price, along with their total remaining
+/// amount.
+#[derive(Debug)]
+struct Level {
+    amount: Amount,
+    orders: Orders,
+}
+
+impl Default for Level {
+    fn default() -> Self {
+        Self {
+            amount: Amount(0),
+            orders: Orders::default(),
+        }
+    }
+}
+
+/// Aggregated view of one orderbook level.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct PriceLevel {
+    pub price: LimitPrice,
+    pub amount: Amount,
+    pub order_count: usize,
+}
+
+/// Best levels of both orderbook sides, each listed from its best price
+/// outwards.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct Depth {
+    pub asks: Vec<PriceLevel>,
+    pub bids: Vec<PriceLevel>,
+}
+
+/// Every order resting in orderbook, as of event `sequence`.
+#[derive(Clone, Debug, Default, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct BookSnapshot {
+    pub sequence: u64,
+    pub asks: Vec<LevelSnapshot>,
+    pub bids: Vec<LevelSnapshot>,
+}
+
+/// Orders resting at one limit price, in time priority.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct LevelSnapshot {
+    pub price: LimitPrice,
+    pub orders: Vec<OrderSnapshot>,
+}
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+pub struct OrderSnapshot {
+    pub id: OrderId,
+    pub remaining: Amount,
+    pub created_at: u128,
+}
+
+impl PriceLevel {
+    fn new(price: LimitPrice, level: &Level) -> Self {
+        Self {
+            price,
+            amount: level.amount,
+            order_count: level.orders.len(),
+        }
+    }
+}
+
+#[derive(Debug)]
+#[cfg_attr(feature = "serde", derive(Serialize))]
+pub struct Orderbook {
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    sides: Sides,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    priorities: HashMap<OrderId, u64>,
+    #[cfg_attr(feature = "serde", serde(skip_serializing))]
+    last_priority: u64,
+    ask_length: Amount,
+    bid_length: Amount,
+}
+
+impl Default for Orderbook {
+    fn default() -> Self {
+        let mut sides = IndexMap::new();
+        sides.insert(OrderSide::Ask, BTreeMap::default());
+        sides.insert(OrderSide::Bid, BTreeMap::default());
+
+        Self {
+            sides,
+            priorities: HashMap::default(),
+            last_priority: 0,
+            ask_length: Amount(0),
+            bid_length: Amount(0),
+        }
+    }
+}
+
+impl Orderbook {
+    fn peek(&self, incoming_order: &Order) -> Option<OrderHandle> {
+        let opposite_side = incoming_order.side.opposite();
+
+        let (_level_limit_price, level) = match incoming_order.side {
+            OrderSide::Ask => self.sides.get(&opposite_side)?.iter().next_back()?,
+            OrderSide::Bid => self.sides.get(&opposite_side)?.iter().next()?,
+        };
+
+        let (_priority, order) = level.orders.iter().next()?;
+
+        Some(*order)
+    }
+
+    fn insert(&mut self, handle: OrderHandle, order: &Order) {
+        // Matching algorithm
+        let (id, side, limit_price, remaining) =
+            (order.id, order.side, order.limit_price, order.remaining);
+
+        // Engine never inserts an order which would overflow totals, and a
+        // level holds no more than its side does
+        match side {
+            OrderSide::Ask => self.ask_length += remaining,
+            OrderSide::Bid => self.bid_length += remaining,
+        }
+
+        // Every insertion goes to the back of its level, even for an order
+        // which was there before
+        self.last_priority += 1;
+        self.priorities.insert(id, self.last_priority);
+
+        let level = self
+            .sides
+            .entry(side)
+            .or_default()
+            .entry(limit_price)
+            .or_default();
+        level.amount += remaining;
+        level.orders.insert(self.last_priority, handle);
+    }
+
+    fn remove(&mut self, order: &Order) -> Option<OrderHandle> {
+        let side = order.side;
+        let limit_price = order.limit_price;
+
+        // Remove order from tree
+        let levels = self.sides.get_mut(&side)?;
+        let level = levels.get_mut(&limit_price)?;
+        let priority = self.priorities.get(&order.id)?;
+        let handle = level.orders.remove(priority)?;
+        self.priorities.remove(&order.id);
+        level.amount = level.amount.saturating_sub(order.remaining);
+
+        // If level is empty, remove it
+        if level.orders.is_empty() {
+            levels.remove(&limit_price);
+        }
+
+        // Remove remaing orders from total count
+        match side {
+            OrderSide::Ask => self.ask_length = self.ask_length.saturating_sub(order.remaining),
+            OrderSide::Bid => self.bid_length = self.bid_length.saturating_sub(order.remaining),
+        }
+
+        Some(handle)
+    }
+
+    /// Takes a traded amount out of total count, for orders which are kept
+    /// linked after being partially filled.
+    fn reduce(&mut self, order: &Order, amount: Amount) {
+        if let Some(level) = self
+            .sides
+            .get_mut(&order.side)
+            .and_then(|levels| levels.get_mut(&order.limit_price))
+        {
+            level.amount = level.amount.saturating_sub(amount);
+        }
+
+        match order.side {
+            OrderSide::Ask => self.ask_length = self.ask_length.saturating_sub(amount),
+            OrderSide::Bid => self.bid_length = self.bid_length.saturating_sub(amount),
+        }
+    }
+
+    /// Whether `incoming_order` would be filled in full, leaving `excluded`
+    /// aside.
+    fn can_fill(&self, incoming_order: &Order, excluded: Option<&Order>) -> bool {
+        let opposite_side = incoming_order.side.opposite();
+        let excluded = excluded.filter(|order| order.side == opposite_side && self.contains(order));
+        let mut remaining = incoming_order.remaining;
+
+        for (limit_price, level) in self.levels(opposite_side) {
+            let crosses = incoming_order.is_market()
+                || match incoming_order.side {
+                    OrderSide::Ask => incoming_order.limit_price <= *limit_price,
+                    OrderSide::Bid => incoming_order.limit_price >= *limit_price,
+                };
+
+            if !crosses {
+                break;
+            }
+
+            let amount = match excluded {
+                Some(order) if order.limit_price == *limit_price => {
+                    level.amount.saturating_sub(order.remaining)
+                }
+                _ => level.amount,
+            };
+
+            if amount >= remaining {
+                return true;
+            }
+
+            remaining -= amount;
+        }
+
+        false
+    }
+
+    /// Total remaining amount resting on `side`.
+    fn length(&self, side: OrderSide) -> Amount {
+        match side {
+            OrderSide::Ask => self.ask_length,
+            OrderSide::Bid => self.bid_length,
+        }
+    }
+
+    /// Levels of `side`, best price first.
+    fn levels(&self, side: OrderSide) -> Box<dyn Iterator<Item = (&LimitPrice, &Level)> + '_> {
+        let Some(levels) = self.sides.get(&side) else {
+            return Box::new(std::iter::empty());
+        };
+
+        match side {
+            OrderSide::Ask => Box::new(levels.iter()),
+            OrderSide::Bid => Box::new(levels.iter().rev()),
+        }
+    }
+
+    /// Orders resting on `side` within `range` of limit prices, lowest price
+    /// first and in queue order within a level.
+    fn handles<'a, R>(&'a self, side: OrderSide, range: R) -> impl Iterator<Item = OrderHandle> + 'a
+    where
+        R: RangeBounds<LimitPrice> + 'a,
+    {
+        // Ranges BTreeMap would panic on hold no level anyway
+        let is_empty = match (range.start_bound(), range.end_bound()) {
+            (Bound::Excluded(start), Bound::Excluded(end)) => start >= end,
+            (
+                Bound::Included(start) | Bound::Excluded(start),
+                Bound::Included(end) | Bound::Excluded(end),
+            ) => start > end,
+            _ => false,
+        };
+
+        self.sides
+            .get(&side)
+            .filter(|_| !is_empty)
+            .map(|levels| levels.range(range))
+            .into_iter()
+            .flatten()
+            .flat_map(|(_limit_price, level)| level.orders.values().copied())
+    }
+
+    /// Best limit price of `side`, leaving `excluded` aside.
+    fn best_price(&self, side: OrderSide, excluded: Option<&Order>) -> Option<LimitPrice> {
+        let excluded = excluded.filter(|order| order.side == side && self.contains(order));
+
+        self.levels(side)
+            .find(|(limit_price, level)| {
+                // A level holding nothing but excluded order is as good as gone
+                !excluded.is_some_and(|order| {
+                    order.limit_price == **limit_price && level.orders.len() == 1
+                })
+            })
+            .map(|(limit_price, _level)| *limit_price)
+    }
+
+    fn contains(&self, order: &Order) -> bool {
+        let Some(priority) = self.priorities.get(&order.id) else {
+            return false;
+        };
+
+        self.sides
+            .get(&order.side)
+            .and_then(|levels| levels.get(&order.limit_price))
+            .is_some_and(|level| level.orders.contains_key(priority))
+    }
+}
+
+impl Orderbook {
+    /// Highest bid level.
+    pub fn best_bid(&self) -> Option<PriceLevel> {
+        let (price, level) = self.sides.get(&OrderSide::Bid)?.iter().next_back()?;
+
+        Some(PriceLevel::new(*price, level))
+    }
+
+    /// Lowest ask level.
+    pub fn best_ask(&self) -> Option<PriceLevel> {
+        let (price, level) = self.sides.get(&OrderSide::Ask)?.iter().next()?;
+
+        Some(PriceLevel::new(*price, level))
+    }
+
+    /// Level of `side` at `price`, if any order rests there.
+    pub fn level(&self, side: OrderSide, price: LimitPrice) -> Option<PriceLevel> {
+        let level = self.sides.get(&side)?.get(&price)?;
+
+        Some(PriceLevel::new(price, level))
+    }
+
+    /// Up to `levels` best price levels of each side.
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            asks: self
+                .levels(OrderSide::Ask)
+                .take(levels)
+                .map(|(price, level)| PriceLevel::new(*price, level))
+                .collect(),
+            bids: self
+                .levels(OrderSide::Bid)
+                .take(levels)
+                .map(|(price, level)| PriceLevel::new(*price, level))
+                .collect(),
+        }
+    }
+
+    /// Distance between best ask and best bid prices, if both sides have
+    /// orders resting.
+    pub fn spread(&self) -> Option<u64> {
+        let (best_bid, best_ask) = (self.best_bid()?, self.best_ask()?);
+
+        Some(best_ask.price.0.saturating_sub(best_bid.price.0))
+    }
+
+    /// Price halfway between best ask and best bid, rounded down, if both
+    /// sides have orders resting.
+    pub fn mid_price(&self) -> Option<LimitPrice> {
+        let (best_bid, best_ask) = (self.best_bid()?, self.best_ask()?);
+
+        Some(LimitPrice(
+            best_bid.price.0 + best_ask.price.0.saturating_sub(best_bid.price.0) / 2,
+        ))
+    }
+}
+
+type Triggers = BTreeMap<LimitPrice, BTreeSet<OrderId>>;
+
+/// Stop orders waiting for last trade price to reach their trigger price.
+#[derive(Debug)]
+struct StopBook {
+    sides: IndexMap<OrderSide, Triggers>,
+    trailing: BTreeSet<OrderId>,
+}
+
+impl Default for StopBook {
+    fn default() -> Self {
+        let mut sides = IndexMap::new();
+        sides.insert(OrderSide::Ask, BTreeMap::default());
+        sides.insert(OrderSide::Bid, BTreeMap::default());
+
+        Self {
+            sides,
+            trailing: BTreeSet::default(),
+        }
+    }
+}
+
+impl StopBook {
+    fn insert(&mut self, order: &Order) {
+        let Some(trigger_price) = order.trigger_price else {
+            return;
+        };
+
+        if order.current_kind == OrderKind::Trailing {
+            self.trailing.insert(order.id);
+        }
+
+        self.sides
+            .entry(order.side)
+            .or_default()
+            .entry(trigger_price)
+            .or_default()
+            .insert(order.id);
+    }
+
+    fn remove(&mut self, order: &Order) -> bool {
+        self.trailing.remove(&order.id);
+
+        let Some(trigger_price) = order.trigger_price else {
+            return false;
+        };
+        let Some(triggers) = self.sides.get_mut(&order.side) else {
+            return false;
+        };
+        let Some(orders) = triggers.get_mut(&trigger_price) else {
+            return false;
+        };
+
+        let removed = orders.remove(&order.id);
+
+        if orders.is_empty() {
+            triggers.remove(&trigger_price);
+        }
+
+        removed
+    }
+
+    /// Takes out one stop order crossed by `last_trade_price`. Bid stops
+    /// trigger at or above their trigger price, ask stops at or below it.
+    fn pop_triggered(&mut self, last_trade_price: LimitPrice) -> Option<OrderId> {
+        let bid_trigger = self
+            .sides
+            .get(&OrderSide::Bid)?
+            .keys()
+            .next()
+            .filter(|trigger_price| **trigger_price <= last_trade_price)
+            .map(|trigger_price| (OrderSide::Bid, *trigger_price));
+        let ask_trigger = self
+            .sides
+            .get(&OrderSide::Ask)?
+            .keys()
+            .next_back()
+            .filter(|trigger_price| **trigger_price >= last_trade_price)
+            .map(|trigger_price| (OrderSide::Ask, *trigger_price));
+
+        let (side, trigger_price) = bid_trigger.or(ask_trigger)?;
+
+        let triggers = self.sides.get_mut(&side)?;
+        let orders = triggers.get_mut(&trigger_price)?;
+        let order_id = orders.pop_first();
+
+        if orders.is_empty() {
+            triggers.remove(&trigger_price);
+        }
+
+        order_id
+    }
+}
+
+/// Something [`TradingEngine`] has to do once engine time reaches it.
+#[derive(Debug)]
+pub enum ScheduledTask {
+    /// Submits an order held back until its activation time.
+    Activate(Pin<Box<Order>>),
+    /// Expires an order whose time in force has run out.
+    Expire(OrderId),
+    /// Cancels an order.
+    Cancel(OrderId),
+}
+
+type ScheduleKey = (u128, u64);
+
+/// Time ordered queue of [`ScheduledTask`]s. Tasks due at the same time come
+/// out in the order they were scheduled.
+#[derive(Debug, Default)]
+pub struct Scheduler {
+    tasks: BTreeMap<ScheduleKey, ScheduledTask>,
+    activations: HashMap<OrderId, ScheduleKey>,
+    cancels: HashMap<OrderId, Vec<ScheduleKey>>,
+    last_sequence: u64,
+}
+
+impl Scheduler {
+    /// Holds `order` back until engine time reaches its `created_at`.
+    pub fn insert(&mut self, order: Pin<Box<Order>>) {
+        let order_id = order.id;
+        let key = self.push(order.created_at, ScheduledTask::Activate(order));
+
+        self.activations.insert(order_id, key);
+    }
+
+    /// Takes back an order still waiting for its activation.
+    pub fn remove(&mut self, order_id: &OrderId) -> Option<Order> {
+        let key = self.activations.remove(order_id)?;
+
+        match self.tasks.remove(&key)? {
+            ScheduledTask::Activate(order) => Some(*Pin::into_inner(order)),
+            _ => None,
+        }
+    }
+
+    /// Orders still waiting for their activation.
+    pub fn orders(&self) -> impl Iterator<Item = &Order> {
+        self.tasks.values().filter_map(|task| match task {
+            ScheduledTask::Activate(order) => Some(Pin::get_ref(order.as_ref())),
+            _ => None,
+        })
+    }
+
+    /// Whether an order is waiting for its activation.
+    #[inline]
+    pub fn contains(&self, order_id: &OrderId) -> bool {
+        self.activations.contains_key(order_id)
+    }
+
+    pub fn schedule_expiry(&mut self, order_id: OrderId, at: u128) {
+        self.push(at, ScheduledTask::Expire(order_id));
+    }
+
+    pub fn schedule_cancel(&mut self, order_id: OrderId, at: u128) {
+        let key = self.push(at, ScheduledTask::Cancel(order_id));
+
+        self.cancels.entry(order_id).or_default().push(key);
+    }
+
+    /// Drops every cancel scheduled for an order.
+    pub fn remove_cancels(&mut self, order_id: &OrderId) {
+        for key in self.cancels.remove(order_id).unwrap_or_default() {
+            self.tasks.remove(&key);
+        }
+    }
+
+    /// Time of the earliest task, if any.
+    pub fn next_due(&self) -> Option<u128> {
+        self.tasks.keys().next().map(|(at, _)| *at)
+    }
+
+    /// Takes out the earliest task due at or before `now`, along with the
+    /// time it was scheduled to.
+    pub fn pop_due(&mut self, now: u128) -> Option<(u128, ScheduledTask)> {
+        let entry = self
+            .tasks
+            .first_entry()
+            .filter(|entry| entry.key().0 <= now)?;
+        let ((at, sequence), task) = entry.remove_entry();
+
+        match &task {
+            ScheduledTask::Activate(order) => {
+                self.activations.remove(&order.id);
+            }
+            ScheduledTask::Cancel(order_id) => {
+                if let Some(keys) = self.cancels.get_mut(order_id) {
+                    keys.retain(|key| key.1 != sequence);
+
+                    if keys.is_empty() {
+                        self.cancels.remove(order_id);
+                    }
+                }
+            }
+            ScheduledTask::Expire(_) => (),
+        }
+
+        Some((at, task))
+    }
+
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.tasks.len()
+    }
+
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.tasks.is_empty()
+    }
+
+    fn push(&mut self, at: u128, task: ScheduledTask) -> ScheduleKey {
+        self.last_sequence += 1;
+
+        let key = (at, self.last_sequence);
+        self.tasks.insert(key, task);
+
+        key
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::time;
+
+    use super::*;
+
+    const EXAMPLE_ORDER: Order = Order {
+        id: OrderId(1),
+        account: None,
+        side: OrderSide::Ask,
+        amount: Amount(100),
+        remaining: Amount(100),
+        limit_price: LimitPrice(500),
+        initial_kind: OrderKind::Limit,
+        current_kind: OrderKind::Limit,
+        status: OrderStatus::Open,
+        trigger_price: None,
+        trailing_offset: None,
+        time_in_force: TimeInForce::Gtc,
+        expires_at: None,
+        created_at: 0,
+    };
+
+    #[test]
+    fn it_works() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=11 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        for i in 12..=17 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.side = OrderSide::Bid;
+            order.amount = Amount(200);
+            order.remaining = order.amount;
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        for event in &trading_engine.events {
+            eprintln!("{:?}", event.response());
+        }
+    }
+
+    #[test]
+    fn cancel() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let cancelled = trading_engine.cancel(&OrderId(2)).unwrap();
+
+        assert_eq!(cancelled.id, OrderId(2));
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert!(!trading_engine.orderbook.contains(&cancelled));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderCancelled { id: OrderId(2) })
+        ));
+
+        // Cancelling twice must not touch the orderbook again
+        assert_eq!(
+            trading_engine.cancel(&OrderId(2)).unwrap_err(),
+            TradingEngineError::UnknownOrder { id: OrderId(2) }
+        );
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+
+        // Matching only reaches orders that are still alive
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(300);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(100));
+        assert_eq!(
+            trading_engine.get(&OrderId(4)).unwrap().remaining,
+            Amount(100)
+        );
+    }
+
+    #[test]
+    fn cancel_partially_filled() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(40);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let cancelled = trading_engine.cancel(&OrderId(1)).unwrap();
+
+        assert_eq!(cancelled.status, OrderStatus::Closed);
+        assert_eq!(cancelled.remaining, Amount(60));
+        assert!(!trading_engine.orderbook.contains(&cancelled));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+        assert!(trading_engine.orderbook.sides[&OrderSide::Ask].is_empty());
+    }
+
+    #[test]
+    fn remove_unlinks_from_orderbook() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        let removed = trading_engine.remove(&OrderId(1)).unwrap();
+
+        assert!(!trading_engine.orderbook.contains(&removed));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderRemovedFromOrderbook { id: OrderId(1) })
+        ));
+    }
+
+    #[test]
+    fn non_crossing_order_keeps_resting_orders() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(500 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(550);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        for i in 1..=3 {
+            let order = trading_engine.get(&OrderId(i)).unwrap();
+            assert_eq!(order.remaining, Amount(100));
+            assert_eq!(order.status, OrderStatus::Open);
+            assert!(trading_engine.orderbook.contains(order));
+        }
+
+        let bid_order = trading_engine.get(&OrderId(4)).unwrap();
+        assert_eq!(bid_order.remaining, Amount(100));
+        assert!(trading_engine.orderbook.contains(bid_order));
+
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(300));
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(100));
+    }
+
+    #[test]
+    fn matching_stops_at_first_non_crossing_level() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(400 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // Crosses only the level at 500, leaving the rest to rest as a bid
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(150);
+        order.remaining = order.amount;
+        order.limit_price = LimitPrice(550);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(2)).unwrap().remaining,
+            Amount(100)
+        );
+        assert_eq!(
+            trading_engine.get(&OrderId(3)).unwrap().remaining,
+            Amount(100)
+        );
+
+        let bid_order = trading_engine.get(&OrderId(4)).unwrap();
+        assert_eq!(bid_order.status, OrderStatus::Partial);
+        assert_eq!(bid_order.remaining, Amount(50));
+
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(50));
+    }
+
+    #[test]
+    fn partially_filled_resting_order_keeps_its_place() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=2 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(30);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let top_order = trading_engine.get(&OrderId(1)).unwrap();
+        assert_eq!(top_order.status, OrderStatus::Partial);
+        assert_eq!(top_order.remaining, Amount(70));
+        assert!(trading_engine.orderbook.contains(top_order));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(170));
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+    }
+
+    #[test]
+    fn amend_keeps_priority_on_decrease() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=2 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        assert!(trading_engine
+            .amend(&OrderId(1), LimitPrice(500), Amount(60))
+            .is_ok());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(160));
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(30);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let top_order = trading_engine.get(&OrderId(1)).unwrap();
+        assert_eq!(top_order.amount, Amount(60));
+        assert_eq!(top_order.remaining, Amount(30));
+        assert_eq!(
+            trading_engine.get(&OrderId(2)).unwrap().remaining,
+            Amount(100)
+        );
+
+        // Amount is total amount, so filled part is kept out of remaining
+        assert!(trading_engine
+            .amend(&OrderId(1), LimitPrice(500), Amount(40))
+            .is_ok());
+        let top_order = trading_engine.get(&OrderId(1)).unwrap();
+        assert_eq!(top_order.remaining, Amount(10));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(110));
+        let responses: Vec<_> = trading_engine
+            .events
+            .iter()
+            .rev()
+            .take(2)
+            .map(TradingEngineEvent::response)
+            .collect();
+        assert!(matches!(
+            responses[..],
+            [
+                TradingEngineResponse::LevelUpdated {
+                    price: LimitPrice(500),
+                    amount: Amount(110),
+                    order_count: 2,
+                    ..
+                },
+                TradingEngineResponse::OrderAmended {
+                    id: OrderId(1),
+                    previous_remaining: Amount(30),
+                    current_remaining: Amount(10),
+                    ..
+                },
+            ]
+        ));
+    }
+
+    #[test]
+    fn amend_loses_priority() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // A larger amount goes to the back of level
+        assert!(trading_engine
+            .amend(&OrderId(1), LimitPrice(500), Amount(150))
+            .is_ok());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(350));
+
+        // So does a price change, even when it comes back
+        assert!(trading_engine
+            .amend(&OrderId(2), LimitPrice(600), Amount(100))
+            .is_ok());
+        assert!(trading_engine
+            .amend(&OrderId(2), LimitPrice(500), Amount(100))
+            .is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.side = OrderSide::Bid;
+        for (id, expected) in [(4, 3), (5, 1), (6, 2)] {
+            order.id = OrderId(id);
+            order.amount = if expected == 1 {
+                Amount(150)
+            } else {
+                Amount(100)
+            };
+            order.remaining = order.amount;
+            assert!(trading_engine.try_insert(order).is_ok());
+            assert!(trading_engine.get(&OrderId(expected)).is_none());
+        }
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+    }
+
+    #[test]
+    fn amend_stop_order() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine
+            .try_insert(stop_order(1, OrderSide::Bid, OrderKind::Stop, 500))
+            .is_ok());
+
+        assert_eq!(
+            trading_engine.amend(&OrderId(1), LimitPrice(500), Amount(50)),
+            Err(TradingEngineError::UnsupportedOrderKind {
+                kind: OrderKind::Stop
+            })
+        );
+        assert_eq!(
+            trading_engine.get(&OrderId(1)).unwrap().remaining,
+            Amount(100)
+        );
+    }
+
+    #[test]
+    fn amend_crossing_price() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        let mut order = EXAMPLE_ORDER;
+        order.limit_price = LimitPrice(600);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(40);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+        assert_eq!(trading_engine.last_trade_price(), None);
+
+        assert!(trading_engine
+            .amend(&OrderId(2), LimitPrice(600), Amount(40))
+            .is_ok());
+        assert_eq!(trading_engine.last_trade_price(), Some(LimitPrice(600)));
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(1)).unwrap().remaining,
+            Amount(60)
+        );
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(0));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(60));
+
+        assert_eq!(
+            trading_engine.amend(&OrderId(2), LimitPrice(600), Amount(40)),
+            Err(TradingEngineError::UnknownOrder { id: OrderId(2) })
+        );
+        assert_eq!(
+            trading_engine.amend(&OrderId(1), LimitPrice(0), Amount(100)),
+            Err(TradingEngineError::InvalidPrice)
+        );
+        assert_eq!(
+            trading_engine.amend(&OrderId(1), LimitPrice(600), Amount(40)),
+            Err(TradingEngineError::InvalidAmount)
+        );
+    }
+
+    #[test]
+    fn engine_assigned_order_ids() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        // Whatever id orders carry is replaced, skipping ids in use
+        let order_ids: Vec<_> = (0..3)
+            .map(|_| trading_engine.submit(EXAMPLE_ORDER, None).unwrap())
+            .collect();
+        assert_eq!(order_ids, [OrderId(1), OrderId(3), OrderId(4)]);
+        assert_eq!(trading_engine.get(&OrderId(4)).unwrap().id, OrderId(4));
+
+        // Rejected orders do not use up an id
+        let mut order = EXAMPLE_ORDER;
+        order.amount = Amount(0);
+        assert_eq!(
+            trading_engine.submit(order, None),
+            Err(TradingEngineError::ZeroAmount)
+        );
+        assert_eq!(trading_engine.submit(EXAMPLE_ORDER, None), Ok(OrderId(5)));
+    }
+
+    #[test]
+    fn client_order_ids() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        let client_order_id = ClientOrderId::new("quote-1");
+
+        let order = EXAMPLE_ORDER.with_account(AccountId(1));
+        let order_id = trading_engine
+            .submit(order, Some(client_order_id.clone()))
+            .unwrap();
+        assert_eq!(
+            trading_engine
+                .get_by_client_order_id(Some(AccountId(1)), &client_order_id)
+                .unwrap()
+                .id,
+            order_id
+        );
+        assert_eq!(
+            trading_engine.client_order_id(&order_id),
+            Some(&client_order_id)
+        );
+        assert!(trading_engine
+            .get_by_client_order_id(Some(AccountId(2)), &client_order_id)
+            .is_none());
+
+        // Client order ids are scoped per account
+        assert_eq!(
+            trading_engine.submit(order, Some(client_order_id.clone())),
+            Err(TradingEngineError::DuplicateClientOrderId)
+        );
+        let order = EXAMPLE_ORDER.with_account(AccountId(2));
+        assert!(trading_engine
+            .submit(order, Some(client_order_id.clone()))
+            .is_ok());
+
+        // Filled orders give their client order id back
+        let mut order = EXAMPLE_ORDER.with_account(AccountId(3));
+        order.side = OrderSide::Bid;
+        assert!(trading_engine.submit(order, None).is_ok());
+        assert!(trading_engine.get(&order_id).is_none());
+        assert!(trading_engine.client_order_id(&order_id).is_none());
+
+        let order = EXAMPLE_ORDER.with_account(AccountId(1));
+        let order_id = trading_engine
+            .submit(order, Some(client_order_id.clone()))
+            .unwrap();
+        assert!(trading_engine.cancel(&order_id).is_ok());
+        assert!(trading_engine
+            .get_by_client_order_id(Some(AccountId(1)), &client_order_id)
+            .is_none());
+    }
+
+    #[test]
+    fn cancel_replace() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=2 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // Same order id can be kept for the replacement
+        let mut order = EXAMPLE_ORDER;
+        order.limit_price = LimitPrice(450);
+        let cancelled = trading_engine.cancel_replace(&OrderId(1), order).unwrap();
+        assert_eq!(cancelled.status, OrderStatus::Cancelled);
+        assert_eq!(cancelled.limit_price, LimitPrice(500));
+        assert_eq!(
+            trading_engine.get(&OrderId(1)).unwrap().limit_price,
+            LimitPrice(450)
+        );
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        assert!(trading_engine.cancel_replace(&OrderId(2), order).is_ok());
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert!(trading_engine.get(&OrderId(3)).is_some());
+
+        let responses: Vec<_> = trading_engine
+            .events
+            .iter()
+            .rev()
+            .take(5)
+            .map(TradingEngineEvent::response)
+            .collect();
+        assert!(matches!(
+            responses[..],
+            [
+                TradingEngineResponse::LevelUpdated { .. },
+                TradingEngineResponse::OrderAddedToOrderbook { id: OrderId(3), .. },
+                TradingEngineResponse::OrderReceived { id: OrderId(3) },
+                TradingEngineResponse::OrderCancelled { id: OrderId(2) },
+                TradingEngineResponse::LevelRemoved { .. },
+            ]
+        ));
+
+        // Orders waiting for their activation can be replaced as well
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        assert!(trading_engine.schedule(order, 100).is_ok());
+        order.id = OrderId(5);
+        assert!(trading_engine.cancel_replace(&OrderId(4), order).is_ok());
+        assert!(trading_engine.scheduler.is_empty());
+        assert!(trading_engine.get(&OrderId(5)).is_some());
+    }
+
+    #[test]
+    fn cancel_replace_rejections() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let last_sequence = trading_engine.last_sequence();
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(5);
+        assert_eq!(
+            trading_engine
+                .cancel_replace(&OrderId(1), order)
+                .unwrap_err(),
+            TradingEngineError::UnknownOrder { id: OrderId(1) }
+        );
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        assert_eq!(
+            trading_engine
+                .cancel_replace(&OrderId(2), order)
+                .unwrap_err(),
+            TradingEngineError::DuplicateOrderId { id: OrderId(3) }
+        );
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(5);
+        order.amount = Amount(0);
+        assert_eq!(
+            trading_engine
+                .cancel_replace(&OrderId(2), order)
+                .unwrap_err(),
+            TradingEngineError::ZeroAmount
+        );
+
+        // Only order 2 and 3 together could fill it, but order 2 goes away
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(5);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(200);
+        order.remaining = order.amount;
+        let order = order.with_time_in_force(TimeInForce::Fok);
+        assert_eq!(
+            trading_engine
+                .cancel_replace(&OrderId(2), order)
+                .unwrap_err(),
+            TradingEngineError::InsufficientLiquidity
+        );
+
+        assert_eq!(trading_engine.last_sequence(), last_sequence);
+        assert_eq!(
+            trading_engine.get(&OrderId(2)).unwrap().status,
+            OrderStatus::Open
+        );
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+    }
+
+    #[test]
+    fn cancel_replace_totals_leave_old_order_out() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        let mut order = EXAMPLE_ORDER;
+        order.amount = Amount(u64::MAX);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        // Book holds no more than before once old order is gone
+        order.id = OrderId(2);
+        assert!(trading_engine.cancel_replace(&OrderId(1), order).is_ok());
+        assert!(trading_engine.get(&OrderId(2)).is_some());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(u64::MAX));
+    }
+
+    #[test]
+    fn mass_cancel_by_side_and_price_range() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for (i, side) in (1..=6).zip([OrderSide::Ask, OrderSide::Bid].into_iter().cycle()) {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.side = side;
+            order.limit_price = match side {
+                OrderSide::Ask => LimitPrice(500 + 10 * i),
+                OrderSide::Bid => LimitPrice(400 + 10 * i),
+            };
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // Asks are resting at 510, 530 and 550
+        let cancelled = trading_engine.cancel_price_range(OrderSide::Ask, LimitPrice(520)..);
+        let cancelled: Vec<_> = cancelled.iter().map(|order| order.id).collect();
+        assert_eq!(cancelled, [OrderId(3), OrderId(5)]);
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(100));
+
+        let cancelled = trading_engine.cancel_side(OrderSide::Bid);
+        assert_eq!(cancelled.len(), 3);
+        assert!(cancelled
+            .iter()
+            .all(|order| order.status == OrderStatus::Cancelled));
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(0));
+        assert!(trading_engine.get(&OrderId(1)).is_some());
+
+        let responses: Vec<_> = trading_engine
+            .events
+            .iter()
+            .rev()
+            .map(TradingEngineEvent::response)
+            .filter(|response| !matches!(response, TradingEngineResponse::LevelRemoved { .. }))
+            .take(4)
+            .collect();
+        assert!(matches!(
+            responses[..],
+            [
+                TradingEngineResponse::OrdersMassCancelled { count: 3 },
+                TradingEngineResponse::OrderCancelled { .. },
+                TradingEngineResponse::OrderCancelled { .. },
+                TradingEngineResponse::OrderCancelled { .. },
+            ]
+        ));
+
+        // Inverted ranges match nothing
+        assert!(trading_engine
+            .cancel_price_range(OrderSide::Ask, LimitPrice(600)..LimitPrice(500))
+            .is_empty());
+        assert!(trading_engine
+            .cancel_price_range(
+                OrderSide::Ask,
+                (
+                    Bound::Excluded(LimitPrice(510)),
+                    Bound::Excluded(LimitPrice(510))
+                )
+            )
+            .is_empty());
+        assert!(trading_engine.get(&OrderId(1)).is_some());
+    }
+
+    #[test]
+    fn mass_cancel_side_includes_stop_orders() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+        assert!(trading_engine
+            .try_insert(stop_order(2, OrderSide::Ask, OrderKind::Stop, 400))
+            .is_ok());
+        assert!(trading_engine
+            .try_insert(stop_order(3, OrderSide::Bid, OrderKind::Stop, 600))
+            .is_ok());
+
+        // Price range only reaches resting orders
+        assert_eq!(
+            trading_engine
+                .cancel_price_range(OrderSide::Ask, ..LimitPrice(600))
+                .len(),
+            1
+        );
+        assert!(trading_engine.get(&OrderId(2)).is_some());
+
+        let cancelled = trading_engine.cancel_side(OrderSide::Ask);
+        let cancelled: Vec<_> = cancelled.iter().map(|order| order.id).collect();
+        assert_eq!(cancelled, [OrderId(2)]);
+        assert!(trading_engine.stops.sides[&OrderSide::Ask].is_empty());
+        assert!(trading_engine.get(&OrderId(3)).is_some());
+    }
+
+    #[test]
+    fn mass_cancel_by_account() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=4 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.account = Some(AccountId(i % 2));
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let order = stop_order(5, OrderSide::Bid, OrderKind::Stop, 600).with_account(AccountId(1));
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(6);
+        order.account = Some(AccountId(1));
+        assert!(trading_engine.schedule(order, 1_000).is_ok());
+
+        let cancelled = trading_engine.cancel_account(AccountId(1));
+        let mut cancelled: Vec<_> = cancelled.iter().map(|order| order.id).collect();
+        cancelled.sort();
+        assert_eq!(cancelled, [OrderId(1), OrderId(3), OrderId(5), OrderId(6)]);
+        assert!(trading_engine.get(&OrderId(5)).is_none());
+        assert!(trading_engine.scheduler.is_empty());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+
+        assert_eq!(trading_engine.cancel_all().len(), 2);
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+        assert!(matches!(
+            trading_engine.events.back().unwrap().response(),
+            TradingEngineResponse::OrdersMassCancelled { count: 2 }
+        ));
+    }
+
+    #[test]
+    fn pricing_policy() {
+        for (policy, price) in [
+            (PricingPolicy::Maker, 510),
+            (PricingPolicy::Taker, 530),
+            (PricingPolicy::Midpoint, 520),
+        ] {
+            let mut trading_engine = TradingEngine::with_capacity(1024);
+            assert_eq!(trading_engine.pricing_policy(), PricingPolicy::Maker);
+            trading_engine.set_pricing_policy(policy);
+
+            let mut order = EXAMPLE_ORDER;
+            order.limit_price = LimitPrice(510);
+            assert!(trading_engine.try_insert(order).is_ok());
+
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(2);
+            order.side = OrderSide::Bid;
+            order.limit_price = LimitPrice(530);
+            assert!(trading_engine.try_insert(order).is_ok());
+
+            assert_eq!(trading_engine.last_trade_price(), Some(LimitPrice(price)));
+            assert!(trading_engine.drain_events().any(|event| matches!(
+                event.response(),
+                TradingEngineResponse::OrderTraded { price: traded, .. } if *traded == price
+            )));
+        }
+    }
+
+    #[test]
+    fn top_of_book() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert_eq!(trading_engine.orderbook().best_ask(), None);
+        assert_eq!(trading_engine.orderbook().spread(), None);
+
+        for (i, limit_price) in (1..=3).zip([510, 510, 530]) {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(limit_price);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(490);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let orderbook = trading_engine.orderbook();
+        assert_eq!(
+            orderbook.best_ask(),
+            Some(PriceLevel {
+                price: LimitPrice(510),
+                amount: Amount(200),
+                order_count: 2,
+            })
+        );
+        assert_eq!(
+            orderbook.best_bid(),
+            Some(PriceLevel {
+                price: LimitPrice(490),
+                amount: Amount(100),
+                order_count: 1,
+            })
+        );
+        assert_eq!(orderbook.spread(), Some(20));
+        assert_eq!(orderbook.mid_price(), Some(LimitPrice(500)));
+
+        // Partial fills shrink level amount but keep its orders
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(5);
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(510);
+        order.amount = Amount(130);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let best_ask = trading_engine.orderbook().best_ask().unwrap();
+        assert_eq!(best_ask.amount, Amount(70));
+        assert_eq!(best_ask.order_count, 1);
+
+        assert!(trading_engine.cancel(&OrderId(2)).is_ok());
+        let best_ask = trading_engine.orderbook().best_ask().unwrap();
+        assert_eq!(best_ask.price, LimitPrice(530));
+        assert_eq!(
+            trading_engine.orderbook().mid_price(),
+            Some(LimitPrice(510))
+        );
+    }
+
+    #[test]
+    fn depth() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for (i, (side, limit_price)) in (1..).zip([
+            (OrderSide::Ask, 510),
+            (OrderSide::Ask, 520),
+            (OrderSide::Ask, 510),
+            (OrderSide::Ask, 530),
+            (OrderSide::Bid, 490),
+            (OrderSide::Bid, 480),
+        ]) {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.side = side;
+            order.limit_price = LimitPrice(limit_price);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let depth = trading_engine.orderbook().depth(2);
+        let asks: Vec<_> = depth
+            .asks
+            .iter()
+            .map(|level| (level.price.0, level.amount.0, level.order_count))
+            .collect();
+        let bids: Vec<_> = depth
+            .bids
+            .iter()
+            .map(|level| (level.price.0, level.amount.0, level.order_count))
+            .collect();
+        assert_eq!(asks, [(510, 200, 2), (520, 100, 1)]);
+        assert_eq!(bids, [(490, 100, 1), (480, 100, 1)]);
+
+        assert_eq!(trading_engine.orderbook().depth(10).asks.len(), 3);
+        assert_eq!(trading_engine.orderbook().depth(0), Depth::default());
+
+        #[cfg(feature = "serde")]
+        {
+            let json = serde_json::to_string(&depth).unwrap();
+            assert_eq!(serde_json::from_str::<Depth>(&json).unwrap(), depth);
+        }
+    }
+
+    #[test]
+    fn order_by_order_snapshot() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for (i, (side, limit_price)) in (1..).zip([
+            (OrderSide::Ask, 520),
+            (OrderSide::Ask, 510),
+            (OrderSide::Ask, 520),
+            (OrderSide::Bid, 490),
+        ]) {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.side = side;
+            order.limit_price = LimitPrice(limit_price);
+            trading_engine.tick(i as u128);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // Order 1 goes behind order 3 once its amount grows, arriving again
+        trading_engine.tick(5);
+        assert!(trading_engine
+            .amend(&OrderId(1), LimitPrice(520), Amount(150))
+            .is_ok());
+
+        let asks: Vec<_> = trading_engine
+            .resting_orders(OrderSide::Ask)
+            .map(|order| order.id)
+            .collect();
+        assert_eq!(asks, [OrderId(2), OrderId(3), OrderId(1)]);
+
+        let snapshot = trading_engine.snapshot();
+        assert_eq!(snapshot.sequence, trading_engine.last_sequence());
+        assert_eq!(snapshot.asks.len(), 2);
+        assert_eq!(snapshot.asks[1].price, LimitPrice(520));
+        assert_eq!(
+            snapshot.asks[1].orders,
+            [
+                OrderSnapshot {
+                    id: OrderId(3),
+                    remaining: Amount(100),
+                    created_at: 3,
+                },
+                OrderSnapshot {
+                    id: OrderId(1),
+                    remaining: Amount(150),
+                    created_at: 5,
+                },
+            ]
+        );
+        assert_eq!(snapshot.bids[0].orders[0].id, OrderId(4));
+    }
+
+    #[test]
+    fn level_events() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for (i, limit_price) in (1..=3).zip([510, 510, 520]) {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(limit_price);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(510);
+        order.amount = Amount(130);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+        assert!(trading_engine.cancel(&OrderId(3)).is_ok());
+
+        let levels: Vec<_> = trading_engine
+            .events
+            .iter()
+            .filter_map(|event| match event.response() {
+                TradingEngineResponse::LevelUpdated {
+                    side,
+                    price,
+                    amount,
+                    order_count,
+                } => Some((*side, price.0, Some((amount.0, *order_count)))),
+                TradingEngineResponse::LevelRemoved { side, price } => Some((*side, price.0, None)),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(
+            levels,
+            [
+                (OrderSide::Ask, 510, Some((100, 1))),
+                (OrderSide::Ask, 510, Some((200, 2))),
+                (OrderSide::Ask, 520, Some((100, 1))),
+                (OrderSide::Ask, 510, Some((100, 1))),
+                (OrderSide::Ask, 510, Some((70, 1))),
+                (OrderSide::Ask, 520, None),
+            ]
+        );
+    }
+
+    #[test]
+    fn queue_priority_follows_arrival() {
+        let clock = ManualClock::default();
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+
+        // Lower ids arriving later queue behind earlier orders
+        for i in [10, 5, 1] {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.amount = Amount(10);
+            order.remaining = order.amount;
+            clock.advance(100);
+            trading_engine.poll();
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let queue: Vec<_> = trading_engine
+            .resting_orders(OrderSide::Ask)
+            .map(|order| (order.id, order.created_at))
+            .collect();
+        assert_eq!(
+            queue,
+            [(OrderId(10), 100), (OrderId(5), 200), (OrderId(1), 300)]
+        );
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(20);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(15);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let makers: Vec<_> = trading_engine
+            .events
+            .iter()
+            .filter_map(|event| match event.response() {
+                TradingEngineResponse::OrderTraded { maker_id, .. } => Some(*maker_id),
+                _ => None,
+            })
+            .collect();
+        assert_eq!(makers, [OrderId(10), OrderId(5)]);
+        assert!(trading_engine.get(&OrderId(10)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(5)).unwrap().remaining,
+            Amount(5)
+        );
+        assert_eq!(
+            trading_engine.get(&OrderId(1)).unwrap().remaining,
+            Amount(10)
+        );
+    }
+
+    #[test]
+    fn trade_events() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=2 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(400 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(150);
+        order.remaining = order.amount;
+        order.limit_price = LimitPrice(600);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let trades = trading_engine
+            .events
+            .iter()
+            .filter_map(|event| match event.response() {
+                TradingEngineResponse::OrderTraded {
+                    trade_id,
+                    maker_id,
+                    taker_id,
+                    price,
+                    amount,
+                    aggressor_side,
+                } => Some((
+                    *trade_id,
+                    *maker_id,
+                    *taker_id,
+                    *price,
+                    *amount,
+                    *aggressor_side,
+                )),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+
+        assert_eq!(
+            trades,
+            vec![
+                (
+                    TradeId(1),
+                    OrderId(1),
+                    OrderId(3),
+                    500,
+                    Amount(100),
+                    OrderSide::Bid
+                ),
+                (
+                    TradeId(2),
+                    OrderId(2),
+                    OrderId(3),
+                    600,
+                    Amount(50),
+                    OrderSide::Bid
+                ),
+            ]
+        );
+    }
+
+    #[test]
+    fn event_sequence() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert_eq!(trading_engine.last_sequence(), 0);
+
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // OrderReceived + OrderAddedToOrderbook + LevelUpdated per order
+        assert_eq!(trading_engine.last_sequence(), 9);
+
+        let sequences = trading_engine
+            .events_since(6)
+            .unwrap()
+            .map(TradingEngineEvent::sequence)
+            .collect::<Vec<_>>();
+        assert_eq!(sequences, vec![7, 8, 9]);
+        assert_eq!(trading_engine.events_since(9).unwrap().count(), 0);
+
+        let drained = trading_engine.drain_events().collect::<Vec<_>>();
+        assert_eq!(drained.len(), 9);
+        assert!(drained
+            .iter()
+            .zip(1..)
+            .all(|(event, sequence)| event.sequence() == sequence));
+
+        // Drained events are no longer available, but new ones are
+        assert_eq!(
+            trading_engine.events_since(3).err(),
+            Some(EventGap {
+                expected: 4,
+                first_available: 10,
+            })
+        );
+        assert!(trading_engine.cancel(&OrderId(1)).is_ok());
+        assert_eq!(
+            trading_engine
+                .events_since(9)
+                .unwrap()
+                .map(TradingEngineEvent::sequence)
+                .collect::<Vec<_>>(),
+            vec![10, 11]
+        );
+    }
+
+    #[test]
+    fn bounded_events() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        trading_engine.set_event_limit(Some(4));
+
+        for i in 1..=5 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        assert_eq!(trading_engine.events.len(), 4);
+        assert_eq!(trading_engine.last_sequence(), 15);
+        assert_eq!(
+            trading_engine.events_since(10).err(),
+            Some(EventGap {
+                expected: 11,
+                first_available: 12,
+            })
+        );
+        assert_eq!(trading_engine.events_since(11).unwrap().count(), 4);
+
+        trading_engine.set_event_limit(Some(1));
+        assert_eq!(trading_engine.events.len(), 1);
+        assert_eq!(trading_engine.events_since(14).unwrap().count(), 1);
+        assert_eq!(trading_engine.events_since(u64::MAX).unwrap().count(), 0);
+    }
+
+    #[test]
+    fn rejections() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        assert_eq!(
+            trading_engine.try_insert(EXAMPLE_ORDER),
+            Err(TradingEngineError::DuplicateOrderId { id: OrderId(1) })
+        );
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.amount = Amount(0);
+        order.remaining = order.amount;
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::ZeroAmount)
+        );
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.limit_price = LimitPrice(0);
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::InvalidPrice)
+        );
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.initial_kind = OrderKind::Trailing;
+        order.current_kind = OrderKind::Trailing;
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::InvalidPrice)
+        );
+
+        assert_eq!(
+            trading_engine.remove(&OrderId(2)).unwrap_err(),
+            TradingEngineError::UnknownOrder { id: OrderId(2) }
+        );
+
+        // Rejected orders leave no trace
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert_eq!(trading_engine.last_sequence(), 3);
+
+        trading_engine.halt();
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::BookHalted)
+        );
+        assert!(trading_engine.cancel(&OrderId(1)).is_ok());
+
+        trading_engine.resume();
+        assert!(trading_engine.try_insert(order).is_ok());
+    }
+
+    #[test]
+    fn amount_overflow_on_stop_trigger() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        let mut order = stop_order(1, OrderSide::Ask, OrderKind::StopLimit, 500);
+        order.limit_price = LimitPrice(600);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.limit_price = LimitPrice(600);
+        order.amount = Amount(u64::MAX - 50);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        // Stop order fitted when received, but not anymore once triggered
+        cross(&mut trading_engine, [3, 4], 500);
+        assert_eq!(triggered_stops(&trading_engine), vec![OrderId(1)]);
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderRejected {
+                id: OrderId(1),
+                error: TradingEngineError::AmountOverflow
+            })
+        ));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(u64::MAX - 50));
+
+        assert!(trading_engine.cancel(&OrderId(2)).is_ok());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+    }
+
+    #[test]
+    fn amount_overflow() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        let mut order = EXAMPLE_ORDER;
+        order.amount = Amount(u64::MAX - 50);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::AmountOverflow)
+        );
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+
+        // Opposite side keeps its own total
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(400);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.amount = Amount(50);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(u64::MAX));
+
+        assert_eq!(
+            trading_engine.amend(&OrderId(3), LimitPrice(500), Amount(51)),
+            Err(TradingEngineError::AmountOverflow)
+        );
+        assert!(trading_engine
+            .amend(&OrderId(3), LimitPrice(500), Amount(40))
+            .is_ok());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(u64::MAX - 10));
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.remaining = Amount(200);
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::RemainingAboveAmount)
+        );
+    }
+
+    #[test]
+    fn market_order_sweeps_opposite_side() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(400 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(250);
+        order.remaining = order.amount;
+        order.limit_price = LimitPrice(0);
+        order.initial_kind = OrderKind::Market;
+        order.current_kind = OrderKind::Market;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(3)).unwrap().remaining,
+            Amount(50)
+        );
+        assert!(trading_engine.get(&OrderId(4)).is_none());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(50));
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(0));
+
+        let prices = trading_engine
+            .events
+            .iter()
+            .filter_map(|event| match event.response() {
+                TradingEngineResponse::OrderTraded { price, .. } => Some(*price),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(prices, vec![500, 600, 700]);
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(
+                TradingEngineResponse::OrderReceivedCompletedBeforeEnterInOrderbook {
+                    id: OrderId(4)
+                }
+            )
+        ));
+    }
+
+    #[test]
+    fn market_order_remainder_expires() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(150);
+        order.remaining = order.amount;
+        order.initial_kind = OrderKind::Market;
+        order.current_kind = OrderKind::Market;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(0));
+        assert!(trading_engine.orderbook.sides[&OrderSide::Bid].is_empty());
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderExpired {
+                id: OrderId(2),
+                remaining: Amount(50)
+            })
+        ));
+
+        // Without any liquidity the whole order expires
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.initial_kind = OrderKind::Market;
+        order.current_kind = OrderKind::Market;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderExpired {
+                id: OrderId(3),
+                remaining: Amount(100)
+            })
+        ));
+    }
+
+    fn stop_order(id: u64, side: OrderSide, kind: OrderKind, trigger_price: u64) -> Order {
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(id);
+        order.side = side;
+        order.initial_kind = kind;
+        order.current_kind = kind;
+        order.with_trigger_price(LimitPrice(trigger_price))
+    }
+
+    fn triggered_stops(trading_engine: &TradingEngine) -> Vec<OrderId> {
+        trading_engine
+            .events
+            .iter()
+            .filter_map(|event| match event.response() {
+                TradingEngineResponse::StopTriggered { id, .. } => Some(*id),
+                _ => None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn stop_order_triggers_as_market_order() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=2 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(400 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = stop_order(3, OrderSide::Bid, OrderKind::Stop, 500);
+        order.amount = Amount(100);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        // Nothing traded yet, so it waits in the stop book
+        assert_eq!(
+            trading_engine.get(&OrderId(3)).unwrap().current_kind,
+            OrderKind::Stop
+        );
+        assert!(!trading_engine
+            .orderbook
+            .contains(trading_engine.get(&OrderId(3)).unwrap()));
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(0));
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(50);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert_eq!(triggered_stops(&trading_engine), vec![OrderId(3)]);
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(2)).unwrap().remaining,
+            Amount(50)
+        );
+        assert_eq!(trading_engine.last_trade_price(), Some(LimitPrice(600)));
+    }
+
+    #[test]
+    fn stop_limit_order_rests_after_trigger() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+
+        let mut order = stop_order(1, OrderSide::Ask, OrderKind::StopLimit, 450);
+        order.limit_price = LimitPrice(440);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        // A trade above trigger price leaves it alone
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        assert!(trading_engine.try_insert(order).is_ok());
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.amount = Amount(10);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(triggered_stops(&trading_engine).is_empty());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(450);
+        assert!(trading_engine.try_insert(order).is_ok());
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(5);
+        order.amount = Amount(190);
+        order.remaining = order.amount;
+        order.limit_price = LimitPrice(450);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert_eq!(triggered_stops(&trading_engine), vec![OrderId(1)]);
+
+        let order = trading_engine.get(&OrderId(1)).unwrap();
+        assert_eq!(order.initial_kind, OrderKind::StopLimit);
+        assert_eq!(order.current_kind, OrderKind::Limit);
+        assert_eq!(order.remaining, Amount(100));
+        assert!(trading_engine.orderbook.contains(order));
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(100));
+    }
+
+    #[test]
+    fn cascading_stop_orders() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=3 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.amount = Amount(50);
+            order.remaining = order.amount;
+            order.limit_price = LimitPrice(400 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        let mut order = stop_order(4, OrderSide::Bid, OrderKind::Stop, 500);
+        order.amount = Amount(50);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+        let mut order = stop_order(5, OrderSide::Bid, OrderKind::Stop, 600);
+        order.amount = Amount(50);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(6);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(10);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        // Order 4 trades up to 600, which then triggers order 5
+        assert_eq!(
+            triggered_stops(&trading_engine),
+            vec![OrderId(4), OrderId(5)]
+        );
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(3)).unwrap().remaining,
+            Amount(40)
+        );
+        assert_eq!(trading_engine.last_trade_price(), Some(LimitPrice(700)));
+    }
+
+    #[test]
+    fn cancel_stop_order() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+
+        assert_eq!(
+            trading_engine.try_insert(stop_order(1, OrderSide::Bid, OrderKind::Stop, 0)),
+            Err(TradingEngineError::InvalidPrice)
+        );
+
+        assert!(trading_engine
+            .try_insert(stop_order(1, OrderSide::Bid, OrderKind::Stop, 500))
+            .is_ok());
+        assert!(trading_engine.cancel(&OrderId(1)).is_ok());
+        assert!(trading_engine.stops.sides[&OrderSide::Bid].is_empty());
+
+        // A trade through trigger price finds no stop order anymore
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(triggered_stops(&trading_engine).is_empty());
+    }
+
+    fn cross(trading_engine: &mut TradingEngine, ids: [u64; 2], price: u64) {
+        for (id, side) in ids.into_iter().zip([OrderSide::Ask, OrderSide::Bid]) {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(id);
+            order.side = side;
+            order.amount = Amount(10);
+            order.remaining = order.amount;
+            order.limit_price = LimitPrice(price);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+    }
+
+    #[test]
+    fn trailing_stop_order() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(1);
+        order.side = OrderSide::Bid;
+        order.limit_price = LimitPrice(490);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        cross(&mut trading_engine, [2, 3], 500);
+
+        let mut order = stop_order(4, OrderSide::Ask, OrderKind::Trailing, 0);
+        order.trigger_price = None;
+        order.amount = Amount(50);
+        order.remaining = order.amount;
+        let order = order.with_trailing_offset(TrailingOffset::Absolute(20));
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert_eq!(
+            trading_engine.get(&OrderId(4)).unwrap().trigger_price,
+            Some(LimitPrice(480))
+        );
+
+        // Price goes up and trigger price follows it
+        cross(&mut trading_engine, [5, 6], 520);
+        assert_eq!(
+            trading_engine.get(&OrderId(4)).unwrap().trigger_price,
+            Some(LimitPrice(500))
+        );
+
+        // Stream tells the trade before the move it caused
+        let responses: Vec<_> = trading_engine
+            .events
+            .iter()
+            .map(TradingEngineEvent::response)
+            .collect();
+        let updated = responses
+            .iter()
+            .position(|response| {
+                matches!(
+                    response,
+                    TradingEngineResponse::TrailingStopUpdated {
+                        id: OrderId(4),
+                        trigger_price: LimitPrice(500)
+                    }
+                )
+            })
+            .unwrap();
+        assert!(matches!(
+            responses[updated - 1],
+            TradingEngineResponse::OrderTraded { price: 520, .. }
+        ));
+
+        // Price goes down, but trigger price stays
+        cross(&mut trading_engine, [7, 8], 510);
+        assert_eq!(
+            trading_engine.get(&OrderId(4)).unwrap().trigger_price,
+            Some(LimitPrice(500))
+        );
+        assert!(triggered_stops(&trading_engine).is_empty());
+
+        cross(&mut trading_engine, [9, 10], 500);
+
+        assert_eq!(triggered_stops(&trading_engine), vec![OrderId(4)]);
+        assert!(trading_engine.get(&OrderId(4)).is_none());
+        assert!(trading_engine.stops.trailing.is_empty());
+        assert_eq!(
+            trading_engine.get(&OrderId(1)).unwrap().remaining,
+            Amount(50)
+        );
+        assert_eq!(trading_engine.last_trade_price(), Some(LimitPrice(490)));
+    }
+
+    #[test]
+    fn trailing_stop_order_without_reference_price() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+
+        let mut order = stop_order(1, OrderSide::Bid, OrderKind::Trailing, 0);
+        order.trigger_price = None;
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::InvalidPrice)
+        );
+
+        let order = order.with_trailing_offset(TrailingOffset::BasisPoints(100));
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::InvalidPrice)
+        );
+
+        // Best ask is used while nothing has been traded
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+        let mut order = order;
+        order.id = OrderId(2);
+        assert!(trading_engine.try_insert(order).is_ok());
+        assert_eq!(
+            trading_engine.get(&OrderId(2)).unwrap().trigger_price,
+            Some(LimitPrice(505))
+        );
+    }
+
+    #[test]
+    fn immediate_or_cancel() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(150);
+        order.remaining = order.amount;
+        let order = order.with_time_in_force(TimeInForce::Ioc);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert_eq!(trading_engine.orderbook.bid_length, Amount(0));
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderExpired {
+                id: OrderId(2),
+                remaining: Amount(50)
+            })
+        ));
+    }
+
+    #[test]
+    fn fill_or_kill() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        for i in 1..=2 {
+            let mut order = EXAMPLE_ORDER;
+            order.id = OrderId(i);
+            order.limit_price = LimitPrice(400 + 100 * i);
+            assert!(trading_engine.try_insert(order).is_ok());
+        }
+
+        // Only 100 is available up to 500
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(150);
+        order.remaining = order.amount;
+        let order = order.with_time_in_force(TimeInForce::Fok);
+        let last_sequence = trading_engine.last_sequence();
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::InsufficientLiquidity)
+        );
+
+        // Rejection changes nothing
+        assert_eq!(trading_engine.last_sequence(), last_sequence);
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+
+        let mut order = order;
+        order.limit_price = LimitPrice(600);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert_eq!(
+            trading_engine.get(&OrderId(2)).unwrap().remaining,
+            Amount(50)
+        );
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(
+                TradingEngineResponse::OrderReceivedCompletedBeforeEnterInOrderbook {
+                    id: OrderId(3)
+                }
+            )
+        ));
+    }
+
+    #[test]
+    fn good_till_date_and_day_expiry() {
+        let mut trading_engine = TradingEngine::with_capacity(1024);
+        trading_engine.tick(1_000);
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(1);
+        assert_eq!(
+            trading_engine.try_insert(order.with_time_in_force(TimeInForce::Gtd(1_000))),
+            Err(TradingEngineError::InvalidExpiry)
+        );
+        assert!(trading_engine
+            .try_insert(order.with_time_in_force(TimeInForce::Gtd(2_000)))
+            .is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        assert!(trading_engine
+            .try_insert(order.with_time_in_force(TimeInForce::Day))
+            .is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        trading_engine.tick(1_999);
+        assert!(trading_engine.get(&OrderId(1)).is_some());
+
+        trading_engine.tick(2_000);
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(200));
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderExpired {
+                id: OrderId(1),
+                remaining: Amount(100)
+            })
+        ));
+
+        // Time does not go backwards
+        trading_engine.tick(0);
+        assert_eq!(trading_engine.now(), 2_000);
+
+        trading_engine.tick(TimeInForce::NANOS_PER_DAY);
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert!(trading_engine.get(&OrderId(3)).is_some());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(100));
+
+        // Orders which never rest leave no expiry behind
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.side = OrderSide::Bid;
+        order.initial_kind = OrderKind::Market;
+        order.current_kind = OrderKind::Market;
+        order.amount = Amount(150);
+        order.remaining = order.amount;
+        let order = order.with_time_in_force(TimeInForce::Gtd(2 * TimeInForce::NANOS_PER_DAY));
+        assert!(trading_engine.try_insert(order).is_ok());
+        assert!(trading_engine.get(&OrderId(4)).is_none());
+        assert!(trading_engine.scheduler.is_empty());
+    }
+
+    #[test]
+    fn clock_stamps_orders_and_events() {
+        let clock = ManualClock::new(1_000);
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+        assert_eq!(trading_engine.now(), 1_000);
+
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+        assert_eq!(trading_engine.get(&OrderId(1)).unwrap().created_at, 1_000);
+
+        clock.advance(500);
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        order.amount = Amount(40);
+        order.remaining = order.amount;
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        let trade = trading_engine
+            .events
+            .iter()
+            .find(|event| matches!(event.response(), TradingEngineResponse::OrderTraded { .. }))
+            .unwrap();
+        assert_eq!(trade.timestamp(), 1_500);
+        assert_eq!(trading_engine.events.front().unwrap().timestamp(), 1_000);
+
+        // Expiry follows clock once engine polls it
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        let order = order.with_time_in_force(TimeInForce::Gtd(2_000));
+        assert!(trading_engine.try_insert(order).is_ok());
+
+        clock.set(1_999);
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(3)).is_some());
+
+        clock.set(2_000);
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+        assert_eq!(trading_engine.events.back().unwrap().timestamp(), 2_000);
+
+        // Engine time does not follow its clock backwards
+        clock.set(0);
+        assert!(trading_engine.cancel(&OrderId(1)).is_ok());
+        assert_eq!(trading_engine.now(), 2_000);
+    }
+
+    #[test]
+    fn scheduler_order() {
+        let mut scheduler = Scheduler::default();
+
+        scheduler.schedule_cancel(OrderId(1), 30);
+        scheduler.schedule_expiry(OrderId(2), 10);
+        scheduler.schedule_cancel(OrderId(3), 10);
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(4);
+        order.created_at = 20;
+        scheduler.insert(Box::pin(order));
+
+        assert_eq!(scheduler.len(), 4);
+        assert_eq!(scheduler.next_due(), Some(10));
+        assert!(scheduler.contains(&OrderId(4)));
+        assert!(scheduler.pop_due(9).is_none());
+
+        assert!(matches!(
+            scheduler.pop_due(20),
+            Some((10, ScheduledTask::Expire(OrderId(2))))
+        ));
+        assert!(matches!(
+            scheduler.pop_due(20),
+            Some((10, ScheduledTask::Cancel(OrderId(3))))
+        ));
+        assert!(matches!(
+            scheduler.pop_due(20),
+            Some((20, ScheduledTask::Activate(order))) if order.id == OrderId(4)
+        ));
+        assert!(!scheduler.contains(&OrderId(4)));
+        assert!(scheduler.pop_due(20).is_none());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(5);
+        order.created_at = 40;
+        scheduler.insert(Box::pin(order));
+
+        assert_eq!(scheduler.remove(&OrderId(5)).unwrap().id, OrderId(5));
+        assert!(scheduler.remove(&OrderId(5)).is_none());
+        assert!(matches!(
+            scheduler.pop_due(u128::MAX),
+            Some((30, ScheduledTask::Cancel(OrderId(1))))
+        ));
+        assert!(scheduler.is_empty());
+    }
+
+    #[test]
+    fn delayed_activation() {
+        let clock = ManualClock::default();
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        order.side = OrderSide::Bid;
+        assert!(trading_engine.schedule(order, 100).is_ok());
+
+        // Scheduled ids are taken already
+        assert_eq!(
+            trading_engine.try_insert(order),
+            Err(TradingEngineError::DuplicateOrderId { id: OrderId(2) })
+        );
+
+        clock.advance(99);
+
+        trading_engine.poll();
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(100));
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+
+        clock.advance(1);
+
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+
+        // Orders are validated again when activated
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(3);
+        assert!(trading_engine.schedule(order, 200).is_ok());
+        trading_engine.halt();
+        clock.advance(100);
+        trading_engine.poll();
+        trading_engine.resume();
+
+        assert!(trading_engine.get(&OrderId(3)).is_none());
+        assert!(matches!(
+            trading_engine
+                .events
+                .back()
+                .map(TradingEngineEvent::response),
+            Some(TradingEngineResponse::OrderRejected {
+                id: OrderId(3),
+                error: TradingEngineError::BookHalted
+            })
+        ));
+    }
+
+    #[test]
+    fn scheduled_cancel() {
+        let clock = ManualClock::default();
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        assert_eq!(
+            trading_engine.schedule_cancel(OrderId(2), 50),
+            Err(TradingEngineError::UnknownOrder { id: OrderId(2) })
+        );
+        assert!(trading_engine.schedule_cancel(OrderId(1), 50).is_ok());
+
+        // Scheduled orders can be cancelled before they are activated
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        assert!(trading_engine.schedule(order, 80).is_ok());
+        assert!(trading_engine.schedule_cancel(OrderId(2), 60).is_ok());
+
+        clock.advance(49);
+
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(1)).is_some());
+
+        clock.advance(50);
+
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(1)).is_none());
+        assert!(trading_engine.get(&OrderId(2)).is_none());
+        assert!(trading_engine.scheduler.is_empty());
+        assert_eq!(trading_engine.orderbook.ask_length, Amount(0));
+
+        let cancelled = trading_engine
+            .events
+            .iter()
+            .filter_map(|event| match event.response() {
+                TradingEngineResponse::OrderCancelled { id } => Some(*id),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(cancelled, vec![OrderId(1), OrderId(2)]);
+    }
+
+    #[test]
+    fn tick_never_moves_time_backwards() {
+        let clock = ManualClock::default();
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+        assert!(trading_engine.schedule(EXAMPLE_ORDER, 100).is_ok());
+
+        // Activation reads a clock which went past tick target already
+        clock.set(150);
+        trading_engine.tick(100);
+
+        assert_eq!(trading_engine.get(&OrderId(1)).unwrap().created_at, 150);
+        assert_eq!(trading_engine.now(), 150);
+    }
+
+    #[test]
+    fn expiry_does_not_reach_reused_order_id() {
+        let clock = ManualClock::default();
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+
+        assert!(trading_engine
+            .try_insert(EXAMPLE_ORDER.with_time_in_force(TimeInForce::Gtd(100)))
+            .is_ok());
+        assert!(trading_engine.cancel(&OrderId(1)).is_ok());
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        clock.advance(100);
+
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(1)).is_some());
+        assert!(trading_engine.scheduler.is_empty());
+    }
+
+    #[test]
+    fn scheduled_cancel_does_not_reach_reused_order_id() {
+        let clock = ManualClock::default();
+        let mut trading_engine = TradingEngine::with_clock(clock.clone());
+
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+        assert!(trading_engine.schedule_cancel(OrderId(1), 100).is_ok());
+        assert!(trading_engine.cancel(&OrderId(1)).is_ok());
+        assert!(trading_engine.scheduler.is_empty());
+        assert!(trading_engine.try_insert(EXAMPLE_ORDER).is_ok());
+
+        clock.advance(100);
+
+        trading_engine.poll();
+        assert!(trading_engine.get(&OrderId(1)).is_some());
+
+        // Nor does one scheduled for an order rejected on activation
+        let mut order = EXAMPLE_ORDER;
+        order.id = OrderId(2);
+        assert!(trading_engine.schedule(order, 200).is_ok());
+        assert!(trading_engine.schedule_cancel(OrderId(2), 300).is_ok());
+        trading_engine.halt();
+        clock.advance(100);
+        trading_engine.poll();
+        trading_engine.resume();
+
+        assert!(trading_engine.scheduler.is_empty());
+    }
 }
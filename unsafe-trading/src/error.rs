@@ -0,0 +1,63 @@
+use std::error::Error;
+use std::fmt::{self, Display};
+
+#[cfg(feature = "serde")]
+use serde::{Deserialize, Serialize};
+
+use crate::order::{OrderId, OrderKind};
+
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(
+    feature = "serde",
+    serde(tag = "reason", rename_all = "SCREAMING_SNAKE_CASE")
+)]
+pub enum TradingEngineError {
+    DuplicateOrderId { id: OrderId },
+    DuplicateClientOrderId,
+    UnknownOrder { id: OrderId },
+    ZeroAmount,
+    InvalidAmount,
+    RemainingAboveAmount,
+    AmountOverflow,
+    InvalidPrice,
+    UnsupportedOrderKind { kind: OrderKind },
+    InvalidExpiry,
+    InsufficientLiquidity,
+    BookHalted,
+}
+
+impl Display for TradingEngineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            TradingEngineError::DuplicateOrderId { id } => {
+                write!(f, "order {} already exists", id.0)
+            }
+            TradingEngineError::DuplicateClientOrderId => {
+                write!(f, "client order id is already in use by this account")
+            }
+            TradingEngineError::UnknownOrder { id } => write!(f, "order {} not found", id.0),
+            TradingEngineError::ZeroAmount => write!(f, "order amount must not be zero"),
+            TradingEngineError::InvalidAmount => {
+                write!(f, "order amount must be above its filled amount")
+            }
+            TradingEngineError::RemainingAboveAmount => {
+                write!(f, "order remaining amount must not exceed its amount")
+            }
+            TradingEngineError::AmountOverflow => {
+                write!(f, "order amount would overflow orderbook totals")
+            }
+            TradingEngineError::InvalidPrice => write!(f, "order limit price is not valid"),
+            TradingEngineError::UnsupportedOrderKind { kind } => {
+                write!(f, "order kind {:?} is not supported", kind)
+            }
+            TradingEngineError::InvalidExpiry => write!(f, "order expiry is in the past"),
+            TradingEngineError::InsufficientLiquidity => {
+                write!(f, "orderbook cannot fill the whole order")
+            }
+            TradingEngineError::BookHalted => write!(f, "orderbook is halted"),
+        }
+    }
+}
+
+impl Error for TradingEngineError {}
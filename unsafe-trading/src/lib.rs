@@ -1,16 +1,46 @@
 #![allow(dead_code, unused)]
+#![cfg_attr(feature = "arena", forbid(unsafe_code))]
 
+mod clock;
 mod core;
+mod error;
 mod order;
+mod replica;
+mod storage;
 
+pub use clock::Clock;
+pub use clock::ManualClock;
+pub use clock::MonotonicClock;
+pub use clock::SystemClock;
+
+pub use crate::core::BookSnapshot;
+pub use crate::core::Depth;
+pub use crate::core::EventGap;
+pub use crate::core::LevelSnapshot;
+pub use crate::core::OrderSnapshot;
 pub use crate::core::Orderbook;
+pub use crate::core::PriceLevel;
+pub use crate::core::ScheduledTask;
 pub use crate::core::Scheduler;
 pub use crate::core::TradingEngine;
+pub use crate::core::TradingEngineEvent;
+pub use crate::core::TradingEngineResponse;
+
+pub use error::TradingEngineError;
+
+pub use replica::BookReplica;
 
+pub use order::AccountId;
 pub use order::Amount;
+pub use order::ClientOrderId;
 pub use order::LimitPrice;
 pub use order::Order;
 pub use order::OrderId;
 pub use order::OrderKind;
 pub use order::OrderSide;
 pub use order::OrderStatus;
+pub use order::PricingPolicy;
+pub use order::TimeInForce;
+pub use order::Trade;
+pub use order::TradeId;
+pub use order::TrailingOffset;
@@ -13,6 +13,71 @@ pub enum OrderKind {
     Market = 2,
     Stop = 3,
     Trailing = 4,
+    StopLimit = 5,
+}
+
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum TimeInForce {
+    /// Good till cancelled.
+    #[default]
+    Gtc,
+    /// Immediate or cancel: whatever does not fill right away is cancelled.
+    Ioc,
+    /// Fill or kill: the order fills completely right away or not at all.
+    Fok,
+    /// Good till date: expires once engine time reaches the given timestamp.
+    Gtd(u128),
+    /// Expires at the end of the day it was accepted.
+    Day,
+}
+
+impl TimeInForce {
+    pub const NANOS_PER_DAY: u128 = 86_400 * 1_000_000_000;
+
+    /// Timestamp at which an order accepted at `now` expires, if ever.
+    pub fn expires_at(&self, now: u128) -> Option<u128> {
+        match self {
+            TimeInForce::Gtd(expires_at) => Some(*expires_at),
+            TimeInForce::Day => Some((now / Self::NANOS_PER_DAY + 1) * Self::NANOS_PER_DAY),
+            TimeInForce::Gtc | TimeInForce::Ioc | TimeInForce::Fok => None,
+        }
+    }
+
+    /// Whether an order may rest in orderbook after matching.
+    #[inline]
+    pub fn can_rest(&self) -> bool {
+        !matches!(self, TimeInForce::Ioc | TimeInForce::Fok)
+    }
+}
+
+/// Price at which two crossing limit orders trade. Market orders have no
+/// price of their own, so trading against one always uses the limit order
+/// price.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
+pub enum PricingPolicy {
+    /// Resting order price.
+    #[default]
+    Maker,
+    /// Incoming order price.
+    Taker,
+    /// Halfway between both prices, rounded down to a whole tick.
+    Midpoint,
+}
+
+impl PricingPolicy {
+    /// Trade price between `taker` and `maker` limit prices.
+    #[inline]
+    pub fn price(&self, taker: LimitPrice, maker: LimitPrice) -> LimitPrice {
+        match self {
+            PricingPolicy::Maker => maker,
+            PricingPolicy::Taker => taker,
+            PricingPolicy::Midpoint => LimitPrice(((taker.0 as u128 + maker.0 as u128) / 2) as u64),
+        }
+    }
 }
 
 #[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
@@ -33,10 +98,11 @@ impl OrderSide {
     }
 }
 
-#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq, Hash)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[cfg_attr(feature = "serde", serde(rename_all = "UPPERCASE"))]
 pub enum OrderStatus {
+    #[default]
     Open = 1,
     Partial = 2,
     Completed = 3,
@@ -44,12 +110,6 @@ pub enum OrderStatus {
     Cancelled = 5,
 }
 
-impl Default for OrderStatus {
-    fn default() -> Self {
-        Self::Open
-    }
-}
-
 #[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(transparent)]
@@ -75,6 +135,66 @@ impl DerefMut for OrderId {
     }
 }
 
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(transparent)]
+pub struct TradeId(pub(crate) u64);
+
+impl TradeId {
+    pub fn new(trade_id: u64) -> Self {
+        Self(trade_id)
+    }
+}
+
+impl Deref for TradeId {
+    type Target = u64;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Order reference chosen by a client, unique among live orders of its
+/// account.
+#[derive(Clone, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(transparent)]
+pub struct ClientOrderId(pub(crate) String);
+
+impl ClientOrderId {
+    pub fn new(client_order_id: impl Into<String>) -> Self {
+        Self(client_order_id.into())
+    }
+}
+
+impl Deref for ClientOrderId {
+    type Target = str;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
+/// Owner of an order, used to act on all orders of one client at once.
+#[derive(Clone, Copy, Debug, Hash, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[repr(transparent)]
+pub struct AccountId(pub(crate) u64);
+
+impl AccountId {
+    pub fn new(account_id: u64) -> Self {
+        Self(account_id)
+    }
+}
+
+impl Deref for AccountId {
+    type Target = u64;
+
+    fn deref(&self) -> &Self::Target {
+        &self.0
+    }
+}
+
 #[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord)]
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 #[repr(transparent)]
@@ -84,6 +204,28 @@ impl LimitPrice {
     pub fn new(limit_price: u64) -> Self {
         Self(limit_price)
     }
+
+    /// Price `ticks` above, or `None` if it does not fit.
+    #[inline]
+    pub fn checked_add(self, ticks: u64) -> Option<Self> {
+        self.0.checked_add(ticks).map(Self)
+    }
+
+    /// Price `ticks` below, or `None` if it would go under zero.
+    #[inline]
+    pub fn checked_sub(self, ticks: u64) -> Option<Self> {
+        self.0.checked_sub(ticks).map(Self)
+    }
+
+    #[inline]
+    pub fn saturating_add(self, ticks: u64) -> Self {
+        Self(self.0.saturating_add(ticks))
+    }
+
+    #[inline]
+    pub fn saturating_sub(self, ticks: u64) -> Self {
+        Self(self.0.saturating_sub(ticks))
+    }
 }
 
 impl Deref for LimitPrice {
@@ -113,6 +255,26 @@ impl Amount {
     pub fn is_zero(&self) -> bool {
         self.0 == 0
     }
+
+    #[inline]
+    pub fn checked_add(self, rhs: Self) -> Option<Self> {
+        self.0.checked_add(rhs.0).map(Self)
+    }
+
+    #[inline]
+    pub fn checked_sub(self, rhs: Self) -> Option<Self> {
+        self.0.checked_sub(rhs.0).map(Self)
+    }
+
+    #[inline]
+    pub fn saturating_add(self, rhs: Self) -> Self {
+        Self(self.0.saturating_add(rhs.0))
+    }
+
+    #[inline]
+    pub fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
 }
 
 impl Deref for Amount {
@@ -157,10 +319,48 @@ impl SubAssign for Amount {
     }
 }
 
+/// Distance kept between a trailing stop order trigger price and the price
+/// it follows.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+#[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
+#[cfg_attr(feature = "serde", serde(rename_all = "SCREAMING_SNAKE_CASE"))]
+pub enum TrailingOffset {
+    /// Fixed number of price ticks.
+    Absolute(u64),
+    /// Fraction of the followed price, in basis points (1/10000).
+    BasisPoints(u64),
+}
+
+impl TrailingOffset {
+    #[inline]
+    pub fn is_zero(&self) -> bool {
+        match self {
+            TrailingOffset::Absolute(offset) | TrailingOffset::BasisPoints(offset) => *offset == 0,
+        }
+    }
+
+    /// Offset in price ticks when following `reference` price.
+    pub fn distance(&self, reference: LimitPrice) -> u64 {
+        match self {
+            TrailingOffset::Absolute(ticks) => *ticks,
+            TrailingOffset::BasisPoints(basis_points) => {
+                (reference.0 as u128 * *basis_points as u128 / 10_000) as u64
+            }
+        }
+    }
+}
+
+/// `self` is the incoming order, trading against `other` resting in
+/// orderbook.
 pub trait Exchangeable {
     type Opposite;
     fn matches_with(&self, other: &Self::Opposite) -> bool;
-    fn trade(&mut self, other: &mut Self::Opposite) -> Option<Trade>;
+    fn trade_with(&mut self, other: &mut Self::Opposite, policy: PricingPolicy) -> Option<Trade>;
+
+    #[inline]
+    fn trade(&mut self, other: &mut Self::Opposite) -> Option<Trade> {
+        self.trade_with(other, PricingPolicy::default())
+    }
 }
 
 #[cfg(test)]
@@ -183,4 +383,56 @@ mod tests {
 
         assert_eq!(amount_1, Amount(10));
     }
+
+    #[test]
+    fn checked_and_saturating_ops() {
+        let max = Amount(u64::MAX);
+
+        assert_eq!(Amount(10).checked_add(Amount(20)), Some(Amount(30)));
+        assert_eq!(max.checked_add(Amount(1)), None);
+        assert_eq!(Amount(20).checked_sub(Amount(10)), Some(Amount(10)));
+        assert_eq!(Amount(10).checked_sub(Amount(20)), None);
+        assert_eq!(max.saturating_add(Amount(1)), max);
+        assert_eq!(Amount(10).saturating_sub(Amount(20)), Amount(0));
+
+        assert_eq!(LimitPrice(10).checked_add(5), Some(LimitPrice(15)));
+        assert_eq!(LimitPrice(u64::MAX).checked_add(1), None);
+        assert_eq!(LimitPrice(10).checked_sub(20), None);
+        assert_eq!(LimitPrice(u64::MAX).saturating_add(1), LimitPrice(u64::MAX));
+        assert_eq!(LimitPrice(10).saturating_sub(20), LimitPrice(0));
+    }
+
+    #[test]
+    fn time_in_force_expiry() {
+        let now = 3 * TimeInForce::NANOS_PER_DAY + 42;
+
+        assert_eq!(TimeInForce::Gtc.expires_at(now), None);
+        assert_eq!(TimeInForce::Ioc.expires_at(now), None);
+        assert_eq!(TimeInForce::Fok.expires_at(now), None);
+        assert_eq!(TimeInForce::Gtd(now + 1).expires_at(now), Some(now + 1));
+        assert_eq!(
+            TimeInForce::Day.expires_at(now),
+            Some(4 * TimeInForce::NANOS_PER_DAY)
+        );
+
+        assert!(TimeInForce::Gtc.can_rest());
+        assert!(TimeInForce::Day.can_rest());
+        assert!(!TimeInForce::Ioc.can_rest());
+        assert!(!TimeInForce::Fok.can_rest());
+    }
+
+    #[test]
+    fn trailing_offset_distance() {
+        assert_eq!(TrailingOffset::Absolute(15).distance(LimitPrice(1000)), 15);
+        assert_eq!(
+            TrailingOffset::BasisPoints(150).distance(LimitPrice(1000)),
+            15
+        );
+        assert_eq!(
+            TrailingOffset::BasisPoints(10_000).distance(LimitPrice(u64::MAX)),
+            u64::MAX
+        );
+        assert!(TrailingOffset::BasisPoints(0).is_zero());
+        assert!(!TrailingOffset::Absolute(1).is_zero());
+    }
 }
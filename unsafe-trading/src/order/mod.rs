@@ -14,6 +14,7 @@ pub use internals::*;
 #[cfg_attr(feature = "serde", derive(Serialize, Deserialize))]
 pub struct Order {
     pub(crate) id: OrderId,
+    pub(crate) account: Option<AccountId>,
     pub(crate) initial_kind: OrderKind,
     pub(crate) current_kind: OrderKind,
     pub(crate) side: OrderSide,
@@ -21,6 +22,11 @@ pub struct Order {
     pub(crate) remaining: Amount,
     pub(crate) limit_price: LimitPrice,
     pub(crate) status: OrderStatus,
+    pub(crate) trigger_price: Option<LimitPrice>,
+    pub(crate) trailing_offset: Option<TrailingOffset>,
+    #[cfg_attr(feature = "serde", serde(default))]
+    pub(crate) time_in_force: TimeInForce,
+    pub(crate) expires_at: Option<u128>,
     pub(crate) created_at: u128,
 }
 
@@ -34,6 +40,7 @@ impl Order {
     ) -> Self {
         Self {
             id,
+            account: None,
             initial_kind: kind,
             current_kind: kind,
             side,
@@ -41,10 +48,38 @@ impl Order {
             remaining: amount,
             limit_price,
             status: OrderStatus::Open,
+            trigger_price: None,
+            trailing_offset: None,
+            time_in_force: TimeInForce::Gtc,
+            expires_at: None,
             created_at: 0,
         }
     }
 
+    /// Sets the last trade price which activates a [`OrderKind::Stop`] or
+    /// [`OrderKind::StopLimit`] order.
+    pub fn with_trigger_price(mut self, trigger_price: LimitPrice) -> Self {
+        self.trigger_price = Some(trigger_price);
+        self
+    }
+
+    pub fn with_account(mut self, account: AccountId) -> Self {
+        self.account = Some(account);
+        self
+    }
+
+    pub fn with_time_in_force(mut self, time_in_force: TimeInForce) -> Self {
+        self.time_in_force = time_in_force;
+        self
+    }
+
+    /// Sets how far a [`OrderKind::Trailing`] order trigger price stays from
+    /// the price it follows.
+    pub fn with_trailing_offset(mut self, trailing_offset: TrailingOffset) -> Self {
+        self.trailing_offset = Some(trailing_offset);
+        self
+    }
+
     pub fn cancel(&mut self) {
         self.update(|order| {
             order.status = if order.remaining == order.amount {
@@ -55,6 +90,57 @@ impl Order {
         });
     }
 
+    #[inline]
+    pub(crate) fn is_market(&self) -> bool {
+        self.current_kind == OrderKind::Market
+    }
+
+    /// Whether the order is waiting for a trigger before it can trade.
+    #[inline]
+    pub(crate) fn is_pending(&self) -> bool {
+        matches!(
+            self.current_kind,
+            OrderKind::Stop | OrderKind::StopLimit | OrderKind::Trailing
+        )
+    }
+
+    /// Moves a trailing stop order trigger price along with `reference`
+    /// price, keeping its offset. Trigger price only ever moves towards
+    /// `reference`, never away from it. Returns whether it has moved.
+    pub(crate) fn trail(&mut self, reference: LimitPrice) -> bool {
+        let Some(trailing_offset) = self.trailing_offset else {
+            return false;
+        };
+
+        let distance = trailing_offset.distance(reference);
+        let trigger_price = match self.side {
+            OrderSide::Ask => reference.saturating_sub(distance),
+            OrderSide::Bid => reference.saturating_add(distance),
+        };
+
+        let moved = match (self.side, self.trigger_price) {
+            (_, None) => true,
+            (OrderSide::Ask, Some(current)) => trigger_price > current,
+            (OrderSide::Bid, Some(current)) => trigger_price < current,
+        };
+
+        if moved {
+            self.update(|order| order.trigger_price = Some(trigger_price));
+        }
+
+        moved
+    }
+
+    /// Turns a triggered stop order into the order it stands for.
+    pub(crate) fn activate(&mut self) {
+        self.update(|order| {
+            order.current_kind = match order.initial_kind {
+                OrderKind::StopLimit => OrderKind::Limit,
+                _ => OrderKind::Market,
+            };
+        });
+    }
+
     pub(crate) fn update<F>(&mut self, mut f: F)
     where
         F: FnMut(&mut Self),
@@ -68,21 +154,26 @@ impl Exchangeable for Order {
 
     #[inline]
     fn matches_with(&self, other: &Self::Opposite) -> bool {
-        if self.side == OrderSide::Ask && other.side == OrderSide::Bid {
+        if self.side == other.side {
+            false
+        } else if self.is_market() || other.is_market() {
+            // Market orders take any price
+            true
+        } else if self.side == OrderSide::Ask {
             self.limit_price.le(&other.limit_price)
-        } else if self.side == OrderSide::Bid && other.side == OrderSide::Ask {
-            self.limit_price.ge(&other.limit_price)
         } else {
-            false
+            self.limit_price.ge(&other.limit_price)
         }
     }
 
-    fn trade(&mut self, other: &mut Self::Opposite) -> Option<Trade> {
-        if self.matches_with(&other) {
+    fn trade_with(&mut self, other: &mut Self::Opposite, policy: PricingPolicy) -> Option<Trade> {
+        if self.matches_with(other) {
             let amount = cmp::min(self.remaining, other.remaining);
-            let price = match self.side {
-                OrderSide::Ask => cmp::max(self.limit_price, other.limit_price).0,
-                OrderSide::Bid => cmp::min(self.limit_price, other.limit_price).0,
+            let price = match (self.is_market(), other.is_market()) {
+                // Market orders have no price of their own
+                (true, _) => other.limit_price.0,
+                (false, true) => self.limit_price.0,
+                (false, false) => policy.price(self.limit_price, other.limit_price).0,
             };
 
             self.update(|order| {
@@ -103,9 +194,11 @@ impl Exchangeable for Order {
                 };
             });
 
+            // Incoming order takes liquidity from the resting one
             Some(Trade {
-                maker_id: self.id,
-                taker_id: other.id,
+                maker_id: other.id,
+                taker_id: self.id,
+                aggressor_side: self.side,
                 amount,
                 price,
                 created_at: 0,
@@ -139,12 +232,12 @@ impl Exchangeable for AskOrder {
 
     #[inline]
     fn matches_with(&self, other: &Self::Opposite) -> bool {
-        self.0.limit_price.le(&other.0.limit_price)
+        self.0.is_market() || other.0.is_market() || self.0.limit_price.le(&other.0.limit_price)
     }
 
-    fn trade(&mut self, other: &mut Self::Opposite) -> Option<Trade> {
-        if self.matches_with(&other) {
-            self.0.trade(&mut other.0)
+    fn trade_with(&mut self, other: &mut Self::Opposite, policy: PricingPolicy) -> Option<Trade> {
+        if self.matches_with(other) {
+            self.0.trade_with(&mut other.0, policy)
         } else {
             None
         }
@@ -157,9 +250,9 @@ impl From<Order> for AskOrder {
     }
 }
 
-impl Into<Order> for AskOrder {
-    fn into(self) -> Order {
-        self.0
+impl From<AskOrder> for Order {
+    fn from(order: AskOrder) -> Self {
+        order.0
     }
 }
 
@@ -186,12 +279,12 @@ impl Exchangeable for BidOrder {
 
     #[inline]
     fn matches_with(&self, other: &Self::Opposite) -> bool {
-        self.0.limit_price.ge(&other.0.limit_price)
+        self.0.is_market() || other.0.is_market() || self.0.limit_price.ge(&other.0.limit_price)
     }
 
-    fn trade(&mut self, other: &mut Self::Opposite) -> Option<Trade> {
-        if self.matches_with(&other) {
-            self.0.trade(&mut other.0)
+    fn trade_with(&mut self, other: &mut Self::Opposite, policy: PricingPolicy) -> Option<Trade> {
+        if self.matches_with(other) {
+            self.0.trade_with(&mut other.0, policy)
         } else {
             None
         }
@@ -204,9 +297,9 @@ impl From<Order> for BidOrder {
     }
 }
 
-impl Into<Order> for BidOrder {
-    fn into(self) -> Order {
-        self.0
+impl From<BidOrder> for Order {
+    fn from(order: BidOrder) -> Self {
+        order.0
     }
 }
 
@@ -237,17 +330,54 @@ impl Ord for Order {
 pub struct Trade {
     pub(crate) maker_id: OrderId,
     pub(crate) taker_id: OrderId,
+    pub(crate) aggressor_side: OrderSide,
     pub(crate) price: u64,
     pub(crate) amount: Amount,
     pub(crate) created_at: u128,
 }
 
 impl Trade {
+    /// Trades incoming `taker` against `maker`, which was resting in
+    /// orderbook.
     pub fn try_new<T>(maker: &mut T, taker: &mut T::Opposite) -> Option<Self>
     where
         T: Exchangeable,
+        T::Opposite: Exchangeable<Opposite = T>,
     {
-        maker.trade(taker)
+        taker.trade(maker)
+    }
+
+    /// Order which was resting in orderbook.
+    #[inline]
+    pub fn maker_id(&self) -> OrderId {
+        self.maker_id
+    }
+
+    /// Incoming order which took liquidity.
+    #[inline]
+    pub fn taker_id(&self) -> OrderId {
+        self.taker_id
+    }
+
+    /// Side of the taker.
+    #[inline]
+    pub fn aggressor_side(&self) -> OrderSide {
+        self.aggressor_side
+    }
+
+    #[inline]
+    pub fn price(&self) -> u64 {
+        self.price
+    }
+
+    #[inline]
+    pub fn amount(&self) -> Amount {
+        self.amount
+    }
+
+    #[inline]
+    pub fn created_at(&self) -> u128 {
+        self.created_at
     }
 }
 
@@ -270,6 +400,7 @@ mod tests {
 
     const EXAMPLE_ORDER: Order = Order {
         id: OrderId(1),
+        account: None,
         side: OrderSide::Ask,
         amount: Amount(100),
         remaining: Amount(100),
@@ -277,6 +408,10 @@ mod tests {
         initial_kind: OrderKind::Limit,
         current_kind: OrderKind::Limit,
         status: OrderStatus::Open,
+        trigger_price: None,
+        trailing_offset: None,
+        time_in_force: TimeInForce::Gtc,
+        expires_at: None,
         created_at: 0,
     };
 
@@ -285,13 +420,13 @@ mod tests {
         // Perfect matching
         {
             let mut ask_order = {
-                let mut order = EXAMPLE_ORDER.clone();
+                let mut order = EXAMPLE_ORDER;
                 order.id = helpers::gen_order_id();
                 order.side = OrderSide::Ask;
                 order
             };
             let mut bid_order = {
-                let mut order = EXAMPLE_ORDER.clone();
+                let mut order = EXAMPLE_ORDER;
                 order.id = helpers::gen_order_id();
                 order.side = OrderSide::Bid;
                 order
@@ -301,18 +436,18 @@ mod tests {
             assert!(ask_order.trade(&mut bid_order).is_some());
         }
 
-        // Taker advantage
+        // Resting order price
         {
             let trade_1a = {
                 let mut ask_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Ask;
                     order.limit_price = LimitPrice(400);
                     order
                 };
                 let mut bid_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Bid;
                     order.limit_price = LimitPrice(500);
@@ -323,14 +458,14 @@ mod tests {
 
             let trade_2a = {
                 let mut ask_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Ask;
                     order.limit_price = LimitPrice(400);
                     order
                 };
                 let mut bid_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Bid;
                     order.limit_price = LimitPrice(500);
@@ -341,14 +476,14 @@ mod tests {
 
             let trade_1b = {
                 let mut ask_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Ask;
                     order.limit_price = LimitPrice(400);
                     AskOrder(order)
                 };
                 let mut bid_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Bid;
                     order.limit_price = LimitPrice(500);
@@ -359,14 +494,14 @@ mod tests {
 
             let trade_2b = {
                 let mut ask_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Ask;
                     order.limit_price = LimitPrice(400);
                     AskOrder(order)
                 };
                 let mut bid_order = {
-                    let mut order = EXAMPLE_ORDER.clone();
+                    let mut order = EXAMPLE_ORDER;
                     order.id = helpers::gen_order_id();
                     order.side = OrderSide::Bid;
                     order.limit_price = LimitPrice(500);
@@ -382,6 +517,107 @@ mod tests {
         }
     }
 
+    #[test]
+    fn pricing_policies() {
+        let order = |side, limit_price| {
+            let mut order = EXAMPLE_ORDER;
+            order.id = helpers::gen_order_id();
+            order.side = side;
+            order.limit_price = LimitPrice(limit_price);
+            order
+        };
+
+        for (policy, ask_incoming, bid_incoming) in [
+            (PricingPolicy::Maker, 500, 400),
+            (PricingPolicy::Taker, 400, 500),
+            (PricingPolicy::Midpoint, 450, 450),
+        ] {
+            // Incoming ask at 400 against resting bid at 500
+            let mut ask_order = order(OrderSide::Ask, 400);
+            let mut bid_order = order(OrderSide::Bid, 500);
+            let trade = ask_order.trade_with(&mut bid_order, policy).unwrap();
+            assert_eq!(trade.price, ask_incoming);
+
+            let mut ask_order = AskOrder(order(OrderSide::Ask, 400));
+            let mut bid_order = BidOrder(order(OrderSide::Bid, 500));
+            let trade = ask_order.trade_with(&mut bid_order, policy).unwrap();
+            assert_eq!(trade.price, ask_incoming);
+
+            // Incoming bid at 500 against resting ask at 400
+            let mut ask_order = order(OrderSide::Ask, 400);
+            let mut bid_order = order(OrderSide::Bid, 500);
+            let trade = bid_order.trade_with(&mut ask_order, policy).unwrap();
+            assert_eq!(trade.price, bid_incoming);
+
+            let mut ask_order = AskOrder(order(OrderSide::Ask, 400));
+            let mut bid_order = BidOrder(order(OrderSide::Bid, 500));
+            let trade = bid_order.trade_with(&mut ask_order, policy).unwrap();
+            assert_eq!(trade.price, bid_incoming);
+
+            // Market orders take limit order price whatever the policy
+            let mut market_order = order(OrderSide::Bid, 0);
+            market_order.initial_kind = OrderKind::Market;
+            market_order.current_kind = OrderKind::Market;
+            let mut ask_order = order(OrderSide::Ask, 400);
+            let trade = market_order.trade_with(&mut ask_order, policy).unwrap();
+            assert_eq!(trade.price, 400);
+        }
+
+        // Midpoint is rounded down
+        assert_eq!(
+            PricingPolicy::Midpoint.price(LimitPrice(401), LimitPrice(400)),
+            LimitPrice(400)
+        );
+        assert_eq!(
+            PricingPolicy::Midpoint.price(LimitPrice(u64::MAX), LimitPrice(u64::MAX)),
+            LimitPrice(u64::MAX)
+        );
+    }
+
+    #[test]
+    fn market_matching() {
+        let mut market_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.id = helpers::gen_order_id();
+            order.side = OrderSide::Bid;
+            order.limit_price = LimitPrice(0);
+            order.initial_kind = OrderKind::Market;
+            order.current_kind = OrderKind::Market;
+            order
+        };
+        let mut ask_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.id = helpers::gen_order_id();
+            order.side = OrderSide::Ask;
+            order.limit_price = LimitPrice(800);
+            order
+        };
+
+        assert!(market_order.matches_with(&ask_order));
+        assert!(ask_order.matches_with(&market_order));
+        assert!(AskOrder(ask_order).matches_with(&BidOrder(market_order)));
+        assert!(BidOrder(market_order).matches_with(&AskOrder(ask_order)));
+
+        // Price always comes from the limit order
+        assert_eq!(market_order.trade(&mut ask_order).unwrap().price, 800);
+
+        let mut market_order = {
+            let mut order = market_order;
+            order.side = OrderSide::Ask;
+            order.remaining = order.amount;
+            order
+        };
+        let mut bid_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.id = helpers::gen_order_id();
+            order.side = OrderSide::Bid;
+            order.limit_price = LimitPrice(300);
+            order
+        };
+
+        assert_eq!(bid_order.trade(&mut market_order).unwrap().price, 300);
+    }
+
     #[test]
     fn no_matching() {
         let trade_1a = {
@@ -597,6 +833,118 @@ mod tests {
         assert!(Trade::try_new(&mut ask_order_1, &mut ask_order_2).is_none());
     }
 
+    #[test]
+    fn trade_attribution() {
+        let order = |side| {
+            let mut order = EXAMPLE_ORDER;
+            order.id = helpers::gen_order_id();
+            order.side = side;
+            order
+        };
+
+        // Incoming order is the taker
+        let mut resting_order = order(OrderSide::Ask);
+        let mut incoming_order = order(OrderSide::Bid);
+        let trade = incoming_order.trade(&mut resting_order).unwrap();
+        assert_eq!(trade.maker_id(), resting_order.id);
+        assert_eq!(trade.taker_id(), incoming_order.id);
+        assert_eq!(trade.aggressor_side(), OrderSide::Bid);
+        assert_eq!(trade.price(), EXAMPLE_ORDER.limit_price.0);
+        assert_eq!(trade.amount(), EXAMPLE_ORDER.amount);
+        assert_eq!(trade.created_at(), 0);
+
+        let mut resting_order = BidOrder(order(OrderSide::Bid));
+        let mut incoming_order = AskOrder(order(OrderSide::Ask));
+        let trade = incoming_order.trade(&mut resting_order).unwrap();
+        assert_eq!(trade.maker_id(), resting_order.id);
+        assert_eq!(trade.taker_id(), incoming_order.id);
+        assert_eq!(trade.aggressor_side(), OrderSide::Ask);
+
+        // Arguments name their roles, whatever the side
+        for (maker_side, taker_side) in [
+            (OrderSide::Ask, OrderSide::Bid),
+            (OrderSide::Bid, OrderSide::Ask),
+        ] {
+            let mut maker = order(maker_side);
+            let mut taker = order(taker_side);
+            let trade = Trade::try_new(&mut maker, &mut taker).unwrap();
+            assert_eq!(trade.maker_id(), maker.id);
+            assert_eq!(trade.taker_id(), taker.id);
+            assert_eq!(trade.aggressor_side(), taker_side);
+        }
+    }
+
+    #[test]
+    fn activate_stop_orders() {
+        let mut stop_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.initial_kind = OrderKind::Stop;
+            order.current_kind = OrderKind::Stop;
+            order.with_trigger_price(LimitPrice(450))
+        };
+        let mut stop_limit_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.initial_kind = OrderKind::StopLimit;
+            order.current_kind = OrderKind::StopLimit;
+            order.with_trigger_price(LimitPrice(450))
+        };
+
+        assert!(stop_order.is_pending());
+        assert!(stop_limit_order.is_pending());
+
+        stop_order.activate();
+        stop_limit_order.activate();
+
+        assert!(!stop_order.is_pending());
+        assert!(!stop_limit_order.is_pending());
+        assert_eq!(stop_order.initial_kind, OrderKind::Stop);
+        assert_eq!(stop_order.current_kind, OrderKind::Market);
+        assert_eq!(stop_limit_order.initial_kind, OrderKind::StopLimit);
+        assert_eq!(stop_limit_order.current_kind, OrderKind::Limit);
+    }
+
+    #[test]
+    fn trail_trigger_price() {
+        let mut ask_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.side = OrderSide::Ask;
+            order.initial_kind = OrderKind::Trailing;
+            order.current_kind = OrderKind::Trailing;
+            order.with_trailing_offset(TrailingOffset::Absolute(10))
+        };
+
+        assert!(ask_order.is_pending());
+        assert!(ask_order.trail(LimitPrice(500)));
+        assert_eq!(ask_order.trigger_price, Some(LimitPrice(490)));
+        assert!(ask_order.trail(LimitPrice(520)));
+        assert_eq!(ask_order.trigger_price, Some(LimitPrice(510)));
+        assert!(!ask_order.trail(LimitPrice(515)));
+        assert_eq!(ask_order.trigger_price, Some(LimitPrice(510)));
+
+        let mut bid_order = {
+            let mut order = EXAMPLE_ORDER;
+            order.side = OrderSide::Bid;
+            order.initial_kind = OrderKind::Trailing;
+            order.current_kind = OrderKind::Trailing;
+            order.with_trailing_offset(TrailingOffset::BasisPoints(200))
+        };
+
+        assert!(bid_order.trail(LimitPrice(500)));
+        assert_eq!(bid_order.trigger_price, Some(LimitPrice(510)));
+        assert!(!bid_order.trail(LimitPrice(600)));
+        assert!(bid_order.trail(LimitPrice(400)));
+        assert_eq!(bid_order.trigger_price, Some(LimitPrice(408)));
+
+        bid_order.activate();
+        assert_eq!(bid_order.initial_kind, OrderKind::Trailing);
+        assert_eq!(bid_order.current_kind, OrderKind::Market);
+
+        // Only trailing orders follow prices
+        let mut limit_order = EXAMPLE_ORDER;
+        assert!(!limit_order.trail(LimitPrice(500)));
+        assert!(limit_order.trigger_price.is_none());
+    }
+
     #[test]
     fn opposite_side() {
         assert_eq!(OrderSide::opposite(&OrderSide::Ask), OrderSide::Bid);
@@ -642,7 +990,7 @@ mod tests {
             order.side = OrderSide::Ask;
 
             let mut ask_order: AskOrder = order.into();
-            let order: Order = ask_order.clone().into();
+            let order: Order = ask_order.into();
 
             (order, ask_order)
         };
@@ -658,7 +1006,7 @@ mod tests {
             order.side = OrderSide::Bid;
 
             let bid_order: BidOrder = order.into();
-            let order: Order = bid_order.clone().into();
+            let order: Order = bid_order.into();
 
             (order, bid_order)
         };
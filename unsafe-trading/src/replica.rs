@@ -0,0 +1,382 @@
+//! Client side copy of an orderbook, kept up to date from engine events.
+
+use std::collections::{BTreeMap, HashMap};
+
+use indexmap::IndexMap;
+
+use crate::core::{
+    BookSnapshot, Depth, EventGap, LevelSnapshot, OrderSnapshot, PriceLevel, TradingEngineEvent,
+    TradingEngineResponse,
+};
+use crate::order::{Amount, LimitPrice, OrderId, OrderSide};
+
+type Queue = IndexMap<OrderId, OrderSnapshot>;
+
+/// Rebuilds orderbook state out of [`TradingEngineEvent`]s.
+///
+/// Aggregated levels follow level events, while resting orders follow order
+/// events, so both views can be checked against each other. Events must be
+/// applied in sequence; a missing event leaves the replica untouched until
+/// it is resynchronized from a [`BookSnapshot`].
+#[derive(Debug)]
+pub struct BookReplica {
+    sequence: u64,
+    levels: IndexMap<OrderSide, BTreeMap<LimitPrice, PriceLevel>>,
+    queues: IndexMap<OrderSide, BTreeMap<LimitPrice, Queue>>,
+    orders: HashMap<OrderId, (OrderSide, LimitPrice)>,
+}
+
+impl Default for BookReplica {
+    fn default() -> Self {
+        let mut levels = IndexMap::new();
+        levels.insert(OrderSide::Ask, BTreeMap::default());
+        levels.insert(OrderSide::Bid, BTreeMap::default());
+
+        let mut queues = IndexMap::new();
+        queues.insert(OrderSide::Ask, BTreeMap::default());
+        queues.insert(OrderSide::Bid, BTreeMap::default());
+
+        Self {
+            sequence: 0,
+            levels,
+            queues,
+            orders: HashMap::default(),
+        }
+    }
+}
+
+impl BookReplica {
+    /// Replica of an engine which has not emitted any event yet.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn from_snapshot(snapshot: &BookSnapshot) -> Self {
+        let mut replica = Self::default();
+
+        for (side, levels) in [
+            (OrderSide::Ask, &snapshot.asks),
+            (OrderSide::Bid, &snapshot.bids),
+        ] {
+            for level in levels {
+                for order in &level.orders {
+                    replica.push_order(side, level.price, *order);
+                }
+
+                replica.levels[&side].insert(
+                    level.price,
+                    PriceLevel {
+                        price: level.price,
+                        amount: level.orders.iter().fold(Amount(0), |amount, order| {
+                            amount.saturating_add(order.remaining)
+                        }),
+                        order_count: level.orders.len(),
+                    },
+                );
+            }
+        }
+
+        replica.sequence = snapshot.sequence;
+
+        replica
+    }
+
+    /// Drops current state in favour of `snapshot`, so events following it
+    /// can be applied again.
+    pub fn resync(&mut self, snapshot: &BookSnapshot) {
+        *self = Self::from_snapshot(snapshot);
+    }
+
+    /// Sequence of the last event applied.
+    #[inline]
+    pub fn sequence(&self) -> u64 {
+        self.sequence
+    }
+
+    /// Applies the event following the last one applied. Events already
+    /// applied are ignored, while an event past the next one is refused.
+    pub fn apply(&mut self, event: &TradingEngineEvent) -> Result<(), EventGap> {
+        // Snapshot a replica was synced from may carry any sequence
+        let expected = self.sequence.saturating_add(1);
+
+        if event.sequence() < expected {
+            return Ok(());
+        }
+
+        if event.sequence() > expected {
+            return Err(EventGap {
+                expected,
+                first_available: event.sequence(),
+            });
+        }
+
+        match *event.response() {
+            TradingEngineResponse::OrderAddedToOrderbook {
+                id,
+                side,
+                price,
+                remaining,
+                created_at,
+            } => self.push_order(
+                side,
+                price,
+                OrderSnapshot {
+                    id,
+                    remaining,
+                    created_at,
+                },
+            ),
+            TradingEngineResponse::OrderPartiallyFilled {
+                id,
+                current_remaining,
+                ..
+            } => {
+                if let Some(order) = self.order_mut(&id) {
+                    order.remaining = current_remaining;
+                }
+            }
+            TradingEngineResponse::OrderAmended {
+                id,
+                limit_price,
+                current_remaining,
+                ..
+            } => {
+                let keeps_priority = self.orders.get(&id).is_some_and(|(_, price)| {
+                    *price == limit_price
+                        && self
+                            .order(&id)
+                            .is_some_and(|order| current_remaining <= order.remaining)
+                });
+
+                // Orders losing their priority come back as new orders
+                if keeps_priority {
+                    if let Some(order) = self.order_mut(&id) {
+                        order.remaining = current_remaining;
+                    }
+                } else {
+                    self.remove_order(&id);
+                }
+            }
+            TradingEngineResponse::OrderRemovedFromOrderbook { id }
+            | TradingEngineResponse::OrderCancelled { id }
+            | TradingEngineResponse::OrderExpired { id, .. } => self.remove_order(&id),
+            TradingEngineResponse::LevelUpdated {
+                side,
+                price,
+                amount,
+                order_count,
+            } => {
+                self.levels[&side].insert(
+                    price,
+                    PriceLevel {
+                        price,
+                        amount,
+                        order_count,
+                    },
+                );
+            }
+            TradingEngineResponse::LevelRemoved { side, price } => {
+                self.levels[&side].remove(&price);
+            }
+            _ => (),
+        }
+
+        self.sequence = event.sequence();
+
+        Ok(())
+    }
+
+    /// Up to `levels` best price levels of each side, as told by level
+    /// events.
+    pub fn depth(&self, levels: usize) -> Depth {
+        Depth {
+            asks: self.levels[&OrderSide::Ask]
+                .values()
+                .take(levels)
+                .copied()
+                .collect(),
+            bids: self.levels[&OrderSide::Bid]
+                .values()
+                .rev()
+                .take(levels)
+                .copied()
+                .collect(),
+        }
+    }
+
+    /// Every resting order, as told by order events.
+    pub fn snapshot(&self) -> BookSnapshot {
+        let side = |levels: Box<dyn Iterator<Item = (&LimitPrice, &Queue)> + '_>| {
+            levels
+                .map(|(price, orders)| LevelSnapshot {
+                    price: *price,
+                    orders: orders.values().copied().collect(),
+                })
+                .collect()
+        };
+
+        BookSnapshot {
+            sequence: self.sequence,
+            asks: side(Box::new(self.queues[&OrderSide::Ask].iter())),
+            bids: side(Box::new(self.queues[&OrderSide::Bid].iter().rev())),
+        }
+    }
+
+    fn push_order(&mut self, side: OrderSide, price: LimitPrice, order: OrderSnapshot) {
+        self.orders.insert(order.id, (side, price));
+        self.queues[&side]
+            .entry(price)
+            .or_default()
+            .insert(order.id, order);
+    }
+
+    fn order(&self, order_id: &OrderId) -> Option<&OrderSnapshot> {
+        let (side, price) = self.orders.get(order_id)?;
+
+        self.queues[side].get(price)?.get(order_id)
+    }
+
+    fn order_mut(&mut self, order_id: &OrderId) -> Option<&mut OrderSnapshot> {
+        let (side, price) = self.orders.get(order_id)?;
+
+        self.queues[side].get_mut(price)?.get_mut(order_id)
+    }
+
+    fn remove_order(&mut self, order_id: &OrderId) {
+        let Some((side, price)) = self.orders.remove(order_id) else {
+            return;
+        };
+
+        let levels = &mut self.queues[&side];
+        if let Some(orders) = levels.get_mut(&price) {
+            orders.shift_remove(order_id);
+
+            if orders.is_empty() {
+                levels.remove(&price);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rand::rngs::StdRng;
+    use rand::{Rng, SeedableRng};
+
+    use super::*;
+
+    use crate::order::{Order, OrderKind};
+    use crate::TradingEngine;
+
+    fn order(id: u64, side: OrderSide, limit_price: u64, amount: u64) -> Order {
+        Order::new(
+            OrderId(id),
+            OrderKind::Limit,
+            side,
+            Amount(amount),
+            LimitPrice(limit_price),
+        )
+    }
+
+    fn assert_replicates(replica: &BookReplica, trading_engine: &TradingEngine) {
+        assert_eq!(replica.sequence(), trading_engine.last_sequence());
+        assert_eq!(replica.snapshot(), trading_engine.snapshot());
+        assert_eq!(
+            replica.depth(usize::MAX),
+            trading_engine.orderbook().depth(usize::MAX)
+        );
+    }
+
+    #[test]
+    fn replicates_engine() {
+        let mut trading_engine = TradingEngine::default();
+        let mut replica = BookReplica::new();
+        let mut rng = StdRng::seed_from_u64(19);
+
+        for id in 1..=500 {
+            let side = if rng.gen() {
+                OrderSide::Ask
+            } else {
+                OrderSide::Bid
+            };
+            let order = order(id, side, rng.gen_range(90..=110), rng.gen_range(1..=50));
+            assert!(trading_engine.try_insert(order).is_ok());
+
+            // Move some resting orders around
+            let order_id = OrderId(rng.gen_range(1..=id));
+            match rng.gen_range(0..5) {
+                0 => {
+                    let _ = trading_engine.cancel(&order_id);
+                }
+                1 => {
+                    let _ = trading_engine.remove(&order_id);
+                }
+                2 => {
+                    let _ = trading_engine.amend(
+                        &order_id,
+                        LimitPrice(rng.gen_range(90..=110)),
+                        Amount(rng.gen_range(1..=50)),
+                    );
+                }
+                _ => (),
+            }
+
+            for event in trading_engine.drain_events() {
+                assert!(replica.apply(&event).is_ok());
+            }
+            assert_replicates(&replica, &trading_engine);
+        }
+    }
+
+    #[test]
+    fn gap_and_resync() {
+        let mut trading_engine = TradingEngine::default();
+        let mut replica = BookReplica::new();
+
+        assert!(trading_engine
+            .try_insert(order(1, OrderSide::Ask, 100, 10))
+            .is_ok());
+        for event in trading_engine.drain_events() {
+            assert!(replica.apply(&event).is_ok());
+        }
+
+        // A gap is refused, leaving replica unchanged
+        let sequence = replica.sequence();
+        assert!(trading_engine
+            .try_insert(order(2, OrderSide::Ask, 100, 10))
+            .is_ok());
+        let events: Vec<_> = trading_engine.drain_events().collect();
+        assert_eq!(
+            replica.apply(&events[1]),
+            Err(EventGap {
+                expected: sequence + 1,
+                first_available: sequence + 2,
+            })
+        );
+        assert_eq!(replica.sequence(), sequence);
+
+        // Replayed events are ignored
+        for event in &events {
+            assert!(replica.apply(event).is_ok());
+            assert!(replica.apply(event).is_ok());
+        }
+        assert_replicates(&replica, &trading_engine);
+
+        // Events lost for good are recovered from a snapshot
+        assert!(trading_engine
+            .try_insert(order(3, OrderSide::Bid, 100, 15))
+            .is_ok());
+        let events: Vec<_> = trading_engine.drain_events().collect();
+        assert!(replica.apply(&events[events.len() - 1]).is_err());
+
+        replica.resync(&trading_engine.snapshot());
+        assert_replicates(&replica, &trading_engine);
+
+        // Nothing follows last possible sequence
+        let mut snapshot = trading_engine.snapshot();
+        snapshot.sequence = u64::MAX;
+        replica.resync(&snapshot);
+        assert!(replica.apply(&events[0]).is_ok());
+        assert_eq!(replica.sequence(), u64::MAX);
+    }
+}
@@ -0,0 +1,121 @@
+use indexmap::IndexMap;
+
+use crate::order::{Order, OrderId};
+
+/// Index of an arena slot, tagged with the generation of the order which was
+/// kept there when the handle was given out.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, Hash)]
+pub(crate) struct OrderHandle {
+    index: u32,
+    generation: u32,
+}
+
+#[derive(Debug)]
+struct Slot {
+    generation: u32,
+    order: Option<Order>,
+}
+
+#[derive(Debug)]
+pub(crate) struct OrderStorage {
+    slots: Vec<Slot>,
+    free: Vec<u32>,
+    orders: IndexMap<OrderId, OrderHandle>,
+}
+
+impl OrderStorage {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            orders: IndexMap::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, order: Order) -> OrderHandle {
+        let order_id = order.id;
+
+        // Reuse a free slot, bumping its generation, or grow the arena
+        let handle = match self.free.pop() {
+            Some(index) => {
+                let slot = &mut self.slots[index as usize];
+                slot.generation = slot.generation.wrapping_add(1);
+                slot.order = Some(order);
+
+                OrderHandle {
+                    index,
+                    generation: slot.generation,
+                }
+            }
+            None => {
+                let index = u32::try_from(self.slots.len()).expect("order arena is full");
+                self.slots.push(Slot {
+                    generation: 0,
+                    order: Some(order),
+                });
+
+                OrderHandle {
+                    index,
+                    generation: 0,
+                }
+            }
+        };
+
+        self.orders.insert(order_id, handle);
+
+        handle
+    }
+
+    pub(crate) fn remove(&mut self, order_id: &OrderId) -> Option<Order> {
+        let handle = self.orders.remove(order_id)?;
+        let order = self.slots[handle.index as usize].order.take();
+
+        self.free.push(handle.index);
+
+        order
+    }
+
+    #[inline]
+    pub(crate) fn handle(&self, order_id: &OrderId) -> Option<OrderHandle> {
+        self.orders.get(order_id).copied()
+    }
+
+    #[inline]
+    pub(crate) fn resolve(&self, handle: OrderHandle) -> &Order {
+        self.slots
+            .get(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.order.as_ref())
+            .expect("stale order handle")
+    }
+
+    #[inline]
+    pub(crate) fn resolve_mut(&mut self, handle: OrderHandle) -> &mut Order {
+        self.slots
+            .get_mut(handle.index as usize)
+            .filter(|slot| slot.generation == handle.generation)
+            .and_then(|slot| slot.order.as_mut())
+            .expect("stale order handle")
+    }
+
+    pub(crate) fn get(&self, order_id: &OrderId) -> Option<&Order> {
+        let handle = self.handle(order_id)?;
+
+        Some(self.resolve(handle))
+    }
+
+    pub(crate) fn get_mut(&mut self, order_id: &OrderId) -> Option<&mut Order> {
+        let handle = self.handle(order_id)?;
+
+        Some(self.resolve_mut(handle))
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Order> {
+        self.orders.values().map(|handle| self.resolve(*handle))
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.orders.len()
+    }
+}
@@ -0,0 +1,88 @@
+//! Where [`TradingEngine`](crate::TradingEngine) keeps its orders.
+//!
+//! Orderbook only links [`OrderHandle`]s, so it never owns an order nor
+//! depends on how orders are kept. Two backends are available:
+//!
+//! - pinned boxes (default): every order is pinned in heap and handles are
+//!   raw pointers to it. Reaching an order is a single dereference, but it
+//!   relies on handles being unlinked before their order is dropped.
+//! - generational arena (`arena` feature): orders are kept in slots of a
+//!   contiguous arena and handles are slot indexes tagged with a generation,
+//!   so a stale handle is caught instead of dereferenced. No `unsafe` code
+//!   is involved.
+
+#[cfg(feature = "arena")]
+mod arena;
+#[cfg(not(feature = "arena"))]
+mod pinned;
+
+#[cfg(feature = "arena")]
+pub(crate) use arena::{OrderHandle, OrderStorage};
+#[cfg(not(feature = "arena"))]
+pub(crate) use pinned::{OrderHandle, OrderStorage};
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::order::{Amount, LimitPrice, Order, OrderId, OrderKind, OrderSide};
+
+    fn order(id: u64) -> Order {
+        Order::new(
+            OrderId(id),
+            OrderKind::Limit,
+            OrderSide::Ask,
+            Amount(100),
+            LimitPrice(500),
+        )
+    }
+
+    #[test]
+    fn insert_resolve_remove() {
+        let mut storage = OrderStorage::with_capacity(2);
+
+        let handle_1 = storage.insert(order(1));
+        let handle_2 = storage.insert(order(2));
+
+        assert_eq!(storage.len(), 2);
+        assert_eq!(storage.resolve(handle_1).id, OrderId(1));
+        assert_eq!(storage.resolve(handle_2).id, OrderId(2));
+        assert_eq!(storage.handle(&OrderId(2)), Some(handle_2));
+
+        storage.resolve_mut(handle_1).remaining = Amount(40);
+        assert_eq!(storage.get(&OrderId(1)).unwrap().remaining, Amount(40));
+
+        storage.get_mut(&OrderId(2)).unwrap().remaining = Amount(10);
+        assert_eq!(storage.resolve(handle_2).remaining, Amount(10));
+
+        let removed = storage.remove(&OrderId(1)).unwrap();
+        assert_eq!(removed.id, OrderId(1));
+        assert_eq!(removed.remaining, Amount(40));
+        assert!(storage.get(&OrderId(1)).is_none());
+        assert!(storage.handle(&OrderId(1)).is_none());
+        assert!(storage.remove(&OrderId(1)).is_none());
+
+        // Remaining handles are not affected by removals
+        let handle_3 = storage.insert(order(3));
+        assert_eq!(storage.resolve(handle_2).id, OrderId(2));
+        assert_eq!(storage.resolve(handle_3).id, OrderId(3));
+
+        let mut ids = storage.values().map(|order| order.id).collect::<Vec<_>>();
+        ids.sort();
+        assert_eq!(ids, vec![OrderId(2), OrderId(3)]);
+    }
+
+    #[cfg(feature = "arena")]
+    #[test]
+    #[should_panic(expected = "stale order handle")]
+    fn stale_handle() {
+        let mut storage = OrderStorage::with_capacity(1);
+
+        let handle = storage.insert(order(1));
+        storage.remove(&OrderId(1));
+
+        // Slot is reused by a new order, with a new generation
+        storage.insert(order(2));
+        storage.resolve(handle);
+    }
+}
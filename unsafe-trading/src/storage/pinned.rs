@@ -0,0 +1,86 @@
+use std::pin::Pin;
+use std::ptr::NonNull;
+
+use indexmap::IndexMap;
+
+use crate::order::{Order, OrderId};
+
+pub(crate) type OrderHandle = NonNull<Order>;
+
+#[derive(Debug)]
+pub(crate) struct OrderStorage {
+    orders: IndexMap<OrderId, Pin<Box<Order>>>,
+}
+
+impl OrderStorage {
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        Self {
+            orders: IndexMap::with_capacity(capacity),
+        }
+    }
+
+    pub(crate) fn insert(&mut self, order: Order) -> OrderHandle {
+        let order_id = order.id;
+
+        // Pin Order in heap
+        let mut boxed = Box::pin(order);
+
+        // Get Order address
+        let pin = Pin::as_mut(&mut boxed);
+        let ptr = NonNull::from(pin.get_mut());
+
+        // Insert Order in index with its pointer
+        self.orders.insert(order_id, boxed);
+
+        ptr
+    }
+
+    pub(crate) fn remove(&mut self, order_id: &OrderId) -> Option<Order> {
+        let pin = self.orders.remove(order_id)?;
+
+        Some(*Pin::into_inner(pin))
+    }
+
+    pub(crate) fn handle(&self, order_id: &OrderId) -> Option<OrderHandle> {
+        let order = self.orders.get(order_id)?;
+
+        Some(NonNull::from(Pin::get_ref(order.as_ref())))
+    }
+
+    #[inline]
+    pub(crate) fn resolve(&self, handle: OrderHandle) -> &Order {
+        // SAFETY: handles are only given out for orders pinned in `orders`,
+        // and engine unlinks them before their order is removed.
+        unsafe { handle.as_ref() }
+    }
+
+    #[inline]
+    pub(crate) fn resolve_mut(&mut self, mut handle: OrderHandle) -> &mut Order {
+        // SAFETY: see `resolve`. Borrowing `self` mutably makes sure no other
+        // reference to the order is alive.
+        unsafe { handle.as_mut() }
+    }
+
+    pub(crate) fn get(&self, order_id: &OrderId) -> Option<&Order> {
+        let order = self.orders.get(order_id)?;
+
+        Some(Pin::get_ref(order.as_ref()))
+    }
+
+    pub(crate) fn get_mut(&mut self, order_id: &OrderId) -> Option<&mut Order> {
+        let order = self.orders.get_mut(order_id)?;
+
+        Some(Pin::get_mut(order.as_mut()))
+    }
+
+    pub(crate) fn values(&self) -> impl Iterator<Item = &Order> {
+        self.orders
+            .values()
+            .map(|order| Pin::get_ref(order.as_ref()))
+    }
+
+    #[inline]
+    pub(crate) fn len(&self) -> usize {
+        self.orders.len()
+    }
+}